@@ -0,0 +1,99 @@
+use crate::{PipeReader, PipeWriter};
+use std::io::{self, Read, Write};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+/// A blocking adapter for [`PipeReader`](struct.PipeReader.html) implementing
+/// [`std::io::Read`](https://doc.rust-lang.org/std/io/trait.Read.html).
+///
+/// Created by [`PipeReader::into_blocking`](struct.PipeReader.html#method.into_blocking). Every
+/// read blocks the current thread until data is available, so it must not be used from within an
+/// async runtime thread.
+#[derive(Debug)]
+pub struct BlockingReader {
+    inner: PipeReader,
+}
+
+/// A blocking adapter for [`PipeWriter`](struct.PipeWriter.html) implementing
+/// [`std::io::Write`](https://doc.rust-lang.org/std/io/trait.Write.html).
+///
+/// Created by [`PipeWriter::into_blocking`](struct.PipeWriter.html#method.into_blocking). Every
+/// write blocks the current thread until the pipe has free capacity, so it must not be used from
+/// within an async runtime thread.
+#[derive(Debug)]
+pub struct BlockingWriter {
+    inner: PipeWriter,
+}
+
+impl PipeReader {
+    /// Converts the reader into a [`BlockingReader`](struct.BlockingReader.html) for
+    /// synchronous code.
+    pub fn into_blocking(self) -> BlockingReader {
+        BlockingReader { inner: self }
+    }
+}
+
+impl PipeWriter {
+    /// Converts the writer into a [`BlockingWriter`](struct.BlockingWriter.html) for
+    /// synchronous code.
+    pub fn into_blocking(self) -> BlockingWriter {
+        BlockingWriter { inner: self }
+    }
+}
+
+impl BlockingReader {
+    /// Returns the underlying async reader.
+    pub fn into_inner(self) -> PipeReader {
+        self.inner
+    }
+}
+
+impl BlockingWriter {
+    /// Returns the underlying async writer.
+    pub fn into_inner(self) -> PipeWriter {
+        self.inner
+    }
+}
+
+impl Read for BlockingReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        block_on(|cx| Pin::new(&mut self.inner).poll_read_impl(cx, buf))
+    }
+}
+
+impl Write for BlockingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        block_on(|cx| Pin::new(&mut self.inner).poll_write_impl(cx, buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        block_on(|cx| Pin::new(&mut self.inner).poll_flush(cx))
+    }
+}
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+// Polls until ready, parking the current thread in between.
+fn block_on<T>(mut poll: impl FnMut(&mut Context) -> Poll<T>) -> T {
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+
+    loop {
+        if let Poll::Ready(output) = poll(&mut cx) {
+            return output;
+        }
+        thread::park();
+    }
+}
@@ -0,0 +1,177 @@
+#[cfg(feature = "bytes")]
+use bytes::{Buf, Bytes};
+use std::collections::VecDeque;
+
+/// The bytes written to the pipe that are not read by every reader yet.
+///
+/// Bytes are addressed by their absolute position in the stream, `start` is the position of the
+/// first byte still buffered. Copied bytes are kept in a ring buffer, so consuming them never moves
+/// the bytes that are left. The ring buffer starts small and doubles as needed, but never grows past
+/// `limit` for bytes that fit into it.
+///
+/// With feature `bytes`, chunks handed over by `PipeWriter::write_bytes` are queued as they are
+/// instead of being copied. Queued chunks are always read before the copied bytes, so a chunk can
+/// only be queued while no copied bytes are waiting.
+pub(crate) struct Buffer {
+    start: usize,
+    bytes: VecDeque<u8>,
+    limit: usize,
+    #[cfg(feature = "bytes")]
+    chunks: VecDeque<Bytes>,
+    #[cfg(feature = "bytes")]
+    chunks_len: usize,
+}
+
+impl Buffer {
+    /// Creates a buffer with room for `capacity` copied bytes, growing up to `limit` of them.
+    pub(crate) fn with_capacity(capacity: usize, limit: usize) -> Buffer {
+        Buffer {
+            start: 0,
+            bytes: VecDeque::with_capacity(capacity),
+            limit,
+            #[cfg(feature = "bytes")]
+            chunks: VecDeque::new(),
+            #[cfg(feature = "bytes")]
+            chunks_len: 0,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        #[cfg(feature = "bytes")]
+        {
+            self.chunks_len + self.bytes.len()
+        }
+        #[cfg(not(feature = "bytes"))]
+        {
+            self.bytes.len()
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(crate) fn start(&self) -> usize {
+        self.start
+    }
+
+    pub(crate) fn end(&self) -> usize {
+        self.start + self.len()
+    }
+
+    /// Iterates over the buffered bytes in reading order.
+    pub(crate) fn slices(&self) -> impl Iterator<Item = &[u8]> {
+        #[cfg(feature = "bytes")]
+        let chunks = self.chunks.iter().map(|chunk| chunk.as_ref());
+        #[cfg(not(feature = "bytes"))]
+        let chunks = std::iter::empty();
+
+        let (front, back) = self.bytes.as_slices();
+        chunks
+            .chain(std::iter::once(front))
+            .chain(std::iter::once(back))
+    }
+
+    /// Iterates over the buffered bytes from the absolute position `pos` onwards.
+    pub(crate) fn slices_from(&self, pos: usize) -> impl Iterator<Item = &[u8]> {
+        let mut skip = pos - self.start;
+        self.slices().filter_map(move |slice| {
+            if skip >= slice.len() {
+                skip -= slice.len();
+                None
+            } else {
+                let slice = &slice[skip..];
+                skip = 0;
+                Some(slice)
+            }
+        })
+    }
+
+    /// The number of copied bytes that fit without growing the ring buffer.
+    #[cfg(all(test, feature = "tokio"))]
+    pub(crate) fn allocated(&self) -> usize {
+        self.bytes.capacity()
+    }
+
+    /// Makes room for `capacity` copied bytes in total without growing again.
+    pub(crate) fn reserve(&mut self, capacity: usize) {
+        self.bytes
+            .reserve_exact(capacity.saturating_sub(self.bytes.len()));
+    }
+
+    pub(crate) fn extend_from_slice(&mut self, data: &[u8]) {
+        let needed = self.bytes.len() + data.len();
+        if needed > self.bytes.capacity() {
+            let grown = self
+                .bytes
+                .capacity()
+                .saturating_mul(2)
+                .min(self.limit)
+                .max(needed);
+            self.bytes.reserve_exact(grown - self.bytes.len());
+        }
+        self.bytes.extend(data);
+    }
+
+    /// Drops `amt` bytes from the front of the buffer.
+    pub(crate) fn consume(&mut self, amt: usize) {
+        self.start += amt;
+
+        #[cfg(feature = "bytes")]
+        let amt = self.consume_chunks(amt);
+
+        let amt = amt.min(self.bytes.len());
+        self.bytes.drain(..amt);
+    }
+
+    /// Consumes up to `amt` bytes from the queued chunks, returning how many are left to consume.
+    #[cfg(feature = "bytes")]
+    fn consume_chunks(&mut self, mut amt: usize) -> usize {
+        while amt > 0 {
+            let chunk = match self.chunks.front_mut() {
+                Some(chunk) => chunk,
+                None => break,
+            };
+
+            if chunk.len() > amt {
+                chunk.advance(amt);
+                self.chunks_len -= amt;
+                return 0;
+            }
+
+            amt -= chunk.len();
+            self.chunks_len -= chunk.len();
+            self.chunks.pop_front();
+        }
+        amt
+    }
+
+    /// The rest of the queued chunk holding the absolute position `pos`, sharing its memory.
+    ///
+    /// Returns `None` if `pos` is past the queued chunks, in the copied bytes.
+    #[cfg(feature = "bytes")]
+    pub(crate) fn chunk_at(&self, pos: usize) -> Option<Bytes> {
+        let mut skip = pos - self.start;
+        for chunk in &self.chunks {
+            if skip < chunk.len() {
+                return Some(chunk.slice(skip..));
+            }
+            skip -= chunk.len();
+        }
+        None
+    }
+
+    #[cfg(feature = "bytes")]
+    pub(crate) fn can_push_chunk(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    #[cfg(feature = "bytes")]
+    pub(crate) fn push_chunk(&mut self, chunk: Bytes) {
+        debug_assert!(self.can_push_chunk());
+        if !chunk.is_empty() {
+            self.chunks_len += chunk.len();
+            self.chunks.push_back(chunk);
+        }
+    }
+}
@@ -0,0 +1,274 @@
+use crate::rate::Rate;
+use crate::reader::EofReason;
+#[cfg(feature = "tokio-util")]
+use crate::state::{lock, unlock};
+use crate::state::{Mutex, OnClose, State, BUFFER_SIZE};
+use crate::{PipeReader, PipeWriter};
+use std::sync::Arc;
+
+/// What a pipe carried until it was closed, passed to the callback set through
+/// [`PipeBuilder::on_close`](struct.PipeBuilder.html#method.on_close).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CloseStats {
+    /// The number of bytes written into the pipe.
+    pub bytes_written: u64,
+    /// The number of bytes read by every reader, see
+    /// [`PipeStats::bytes_read`](struct.PipeStats.html#structfield.bytes_read).
+    pub bytes_read: u64,
+    /// Why the pipe was closed.
+    pub reason: EofReason,
+}
+
+/// Configures and creates a pipe, for the options [`pipe`](fn.pipe.html) doesn't take.
+///
+/// ```
+/// let (writer, reader) = async_pipe::PipeBuilder::new()
+///     .capacity(64)
+///     .flush_on_drop(false)
+///     .build();
+/// ```
+#[derive(Debug)]
+pub struct PipeBuilder {
+    capacity: usize,
+    buffer_capacity: Option<usize>,
+    initial: Vec<u8>,
+    flush_on_drop: bool,
+    coalesce: usize,
+    watermarks: Option<(usize, usize)>,
+    content_length: Option<u64>,
+    rate_tracking: bool,
+    poll_budget: Option<usize>,
+    on_close: Option<OnClose>,
+    #[cfg(feature = "tokio-util")]
+    cancellation: Option<tokio_util::sync::CancellationToken>,
+    name: Option<String>,
+}
+
+impl Default for PipeBuilder {
+    fn default() -> PipeBuilder {
+        PipeBuilder {
+            capacity: BUFFER_SIZE,
+            buffer_capacity: None,
+            initial: Vec::new(),
+            flush_on_drop: true,
+            coalesce: 0,
+            watermarks: None,
+            content_length: None,
+            rate_tracking: false,
+            poll_budget: None,
+            on_close: None,
+            #[cfg(feature = "tokio-util")]
+            cancellation: None,
+            name: None,
+        }
+    }
+}
+
+impl PipeBuilder {
+    /// Creates a builder with the same options as [`pipe`](fn.pipe.html).
+    pub fn new() -> PipeBuilder {
+        PipeBuilder::default()
+    }
+
+    /// Buffers up to `capacity` bytes between the two halves, 1024 by default.
+    ///
+    /// The writer can make progress until `capacity` bytes are waiting to be read, only then does
+    /// it wait for the reader.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn capacity(mut self, capacity: usize) -> PipeBuilder {
+        assert!(
+            capacity > 0,
+            "{}: capacity must be non-zero",
+            env!("CARGO_PKG_NAME")
+        );
+
+        self.capacity = capacity;
+        self
+    }
+
+    /// Allocates room for `buffer_capacity` bytes up front.
+    ///
+    /// By default the buffer starts small and grows as it fills, up to the capacity of the pipe.
+    /// This only sizes the first allocation of the buffer, it doesn't limit how much is buffered. A
+    /// value matching the typical burst of writes avoids growing the buffer while it is written.
+    pub fn buffer_capacity(mut self, buffer_capacity: usize) -> PipeBuilder {
+        self.buffer_capacity = Some(buffer_capacity);
+        self
+    }
+
+    /// Preloads the pipe with `initial`, the reader reads them before anything written.
+    ///
+    /// The preloaded bytes count against the capacity of the pipe until they are read, even if
+    /// there are more of them than fit into it.
+    pub fn initial(mut self, initial: impl Into<Vec<u8>>) -> PipeBuilder {
+        self.initial = initial.into();
+        self
+    }
+
+    /// Whether readers can still read the bytes left in the pipe once every writer is dropped.
+    ///
+    /// This is the default. If turned off, the bytes are discarded when the last writer is dropped
+    /// and readers see EOF right away. Closing the pipe explicitly always leaves them readable.
+    pub fn flush_on_drop(mut self, flush_on_drop: bool) -> PipeBuilder {
+        self.flush_on_drop = flush_on_drop;
+        self
+    }
+
+    /// Lets small writes pile up until `threshold` bytes are buffered before waking the readers.
+    ///
+    /// Readers are also woken when the pipe is full, flushed or closed. A reader polling on its
+    /// own still sees the buffered bytes right away. Off by default, every write wakes the
+    /// readers.
+    pub fn coalesce(mut self, threshold: usize) -> PipeBuilder {
+        self.coalesce = threshold;
+        self
+    }
+
+    /// Lets the writers wait once `high` bytes are buffered, and wakes them again only once fewer
+    /// than `low` bytes are left.
+    ///
+    /// This saves waking a writer for every few bytes the reader consumes from a full pipe. A
+    /// `high` mark above the capacity has no effect, the writers always wait at the capacity.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `high` is zero or `low` is greater than `high`.
+    pub fn watermarks(mut self, low: usize, high: usize) -> PipeBuilder {
+        assert!(
+            high > 0 && low <= high,
+            "{}: watermarks must satisfy low <= high and high > 0",
+            env!("CARGO_PKG_NAME")
+        );
+
+        self.watermarks = Some((low, high));
+        self
+    }
+
+    /// Declares the total number of bytes that will be written into the pipe.
+    ///
+    /// It is only a hint, reported as the upper bound of
+    /// [`PipeReader::size_hint`](struct.PipeReader.html#method.size_hint). Writes are not limited
+    /// by it.
+    pub fn content_length(mut self, len: u64) -> PipeBuilder {
+        self.content_length = Some(len);
+        self
+    }
+
+    /// Keeps an estimate of the throughput of both halves, reported by
+    /// [`PipeWriter::bytes_per_sec`](struct.PipeWriter.html#method.bytes_per_sec) and
+    /// [`PipeReader::bytes_per_sec`](struct.PipeReader.html#method.bytes_per_sec).
+    ///
+    /// Off by default, it adds a timestamp to every read and write.
+    pub fn with_rate_tracking(mut self) -> PipeBuilder {
+        self.rate_tracking = true;
+        self
+    }
+
+    /// Makes each half yield to the scheduler after `budget` reads or writes completed in a row.
+    ///
+    /// On a single threaded runtime a writer that never fills the pipe, or a reader that never
+    /// drains it, would otherwise keep running without giving the other half a chance. A half that
+    /// used up its budget returns `Pending` once, waking itself right away. Off by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `budget` is zero.
+    pub fn poll_budget(mut self, budget: usize) -> PipeBuilder {
+        assert!(
+            budget > 0,
+            "{}: poll budget must be non-zero",
+            env!("CARGO_PKG_NAME")
+        );
+
+        self.poll_budget = Some(budget);
+        self
+    }
+
+    /// Calls `f` once the pipe is closed, by whichever half closes it first: closing either half,
+    /// shutting down the writers, dropping every writer or every reader.
+    ///
+    /// It runs exactly once per pipe, on the task closing it, once the pipe is unlocked again.
+    /// Bytes the readers read after the pipe is closed are not counted in the stats.
+    pub fn on_close(mut self, f: impl FnOnce(CloseStats) + Send + 'static) -> PipeBuilder {
+        self.on_close = Some(OnClose(Box::new(f)));
+        self
+    }
+
+    /// Ties the pipe to `token`: cancelling the token closes the pipe, and closing the pipe, or
+    /// dropping every reader or every writer, cancels the token.
+    ///
+    /// A pipe tied to a token can't be reopened with `reset`.
+    ///
+    /// # Panics
+    ///
+    /// [`build`](#method.build) panics if it is not called from within a tokio runtime, it spawns
+    /// a task waiting for the token.
+    #[cfg(feature = "tokio-util")]
+    pub fn with_cancellation(mut self, token: tokio_util::sync::CancellationToken) -> PipeBuilder {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Labels the pipe with `name`, which shows up in error messages, `Debug` output and, with
+    /// feature `tracing`, the span of the pipe.
+    pub fn name(mut self, name: impl Into<String>) -> PipeBuilder {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Creates the pipe.
+    pub fn build(self) -> (PipeWriter, PipeReader) {
+        let mut state = State::new(self.capacity);
+        if let Some(buffer_capacity) = self.buffer_capacity {
+            state.buffer.reserve(buffer_capacity);
+        }
+        state.buffer.extend_from_slice(&self.initial);
+        state.flush_on_drop = self.flush_on_drop;
+        state.coalesce = self.coalesce;
+        state.content_length = self.content_length;
+        state.poll_budget = self.poll_budget;
+        state.on_close = self.on_close;
+        if self.rate_tracking {
+            state.write_rate = Some(Rate::new());
+            state.read_rate = Some(Rate::new());
+        }
+        if let Some((low, high)) = self.watermarks {
+            state.low_watermark = low;
+            state.high_watermark = high;
+        }
+        if let Some(name) = self.name {
+            state.set_name(name);
+        }
+        #[cfg(feature = "tokio-util")]
+        {
+            state.cancellation = self.cancellation.clone();
+        }
+        let shared_state = Arc::new(Mutex::new(state));
+
+        #[cfg(feature = "tokio-util")]
+        if let Some(token) = self.cancellation {
+            let state = Arc::downgrade(&shared_state);
+            tokio::spawn(async move {
+                token.cancelled().await;
+                // The token is also cancelled once the pipe is closed, the task ends either way.
+                if let Some(state) = state.upgrade() {
+                    let mut state = lock(&state);
+                    state.mark_closed(EofReason::Aborted);
+                    state.wake_readers();
+                    state.wake_writers();
+                    trace!(state, "close");
+                    unlock(state);
+                }
+            });
+        }
+
+        let w = PipeWriter::new(Arc::clone(&shared_state));
+
+        let r = PipeReader::new(Arc::clone(&shared_state));
+
+        (w, r)
+    }
+}
@@ -0,0 +1,91 @@
+use crate::PipeReader;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// A [`PipeReader`](struct.PipeReader.html) followed by a second reader.
+///
+/// Created by [`PipeReader::chain`](struct.PipeReader.html#method.chain). Reads return the bytes
+/// of the pipe until its EOF, then the ones of the second reader. A single read never mixes
+/// bytes of both. Implements `AsyncRead` for the enabled features, if the second reader
+/// implements the matching `AsyncRead`.
+#[derive(Debug)]
+pub struct ChainReader<R> {
+    first: PipeReader,
+    second: R,
+    first_done: bool,
+}
+
+impl PipeReader {
+    /// Reads the pipe to EOF, then `next`.
+    pub fn chain<R>(self, next: R) -> ChainReader<R> {
+        ChainReader {
+            first: self,
+            second: next,
+            first_done: false,
+        }
+    }
+}
+
+impl<R> ChainReader<R> {
+    /// Returns both readers back.
+    pub fn into_inner(self) -> (PipeReader, R) {
+        (self.first, self.second)
+    }
+
+    // Reads from the pipe, `None` once it hit EOF and the second reader takes over.
+    #[cfg(feature = "futures")]
+    fn poll_read_first(
+        &mut self,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Option<Poll<io::Result<usize>>> {
+        if self.first_done || buf.is_empty() {
+            return None;
+        }
+
+        match Pin::new(&mut self.first).poll_read_impl(cx, buf) {
+            Poll::Ready(Ok(0)) => {
+                self.first_done = true;
+                None
+            }
+            poll => Some(poll),
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<R: tokio::io::AsyncRead + Unpin> tokio::io::AsyncRead for ChainReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut tokio::io::ReadBuf,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        if !this.first_done && buf.remaining() > 0 {
+            match std::task::ready!(Pin::new(&mut this.first).poll_read_buf(cx, buf, usize::MAX))? {
+                0 => this.first_done = true,
+                _ => return Poll::Ready(Ok(())),
+            }
+        }
+
+        Pin::new(&mut this.second).poll_read(cx, buf)
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<R: futures::io::AsyncRead + Unpin> futures::io::AsyncRead for ChainReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        match this.poll_read_first(cx, buf) {
+            Some(poll) => poll,
+            None => Pin::new(&mut this.second).poll_read(cx, buf),
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use crate::PipeReader;
+use crc32fast::Hasher;
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// A [`PipeReader`](struct.PipeReader.html) computing the CRC32 of the bytes it reads.
+///
+/// Created by [`PipeReader::with_checksum`](struct.PipeReader.html#method.with_checksum). Reads
+/// return the bytes unchanged, [`finalize`](#method.finalize) gives the checksum of the stream
+/// once EOF is reached.
+pub struct ChecksumReader {
+    inner: PipeReader,
+    hasher: Hasher,
+    len: u64,
+}
+
+impl PipeReader {
+    /// Keeps a running CRC32 of the bytes read.
+    pub fn with_checksum(self) -> ChecksumReader {
+        ChecksumReader {
+            inner: self,
+            hasher: Hasher::new(),
+            len: 0,
+        }
+    }
+}
+
+impl ChecksumReader {
+    /// Returns the CRC32 of the bytes read so far, the one of the whole stream after EOF.
+    pub fn finalize(&self) -> u32 {
+        self.hasher.clone().finalize()
+    }
+
+    /// Returns the number of bytes read so far.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns `true` if no bytes were read yet.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the reader back.
+    pub fn into_inner(self) -> PipeReader {
+        self.inner
+    }
+
+    #[cfg(feature = "futures")]
+    fn poll_read_checksummed(
+        &mut self,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        match Pin::new(&mut self.inner).poll_read_impl(cx, buf) {
+            Poll::Ready(Ok(n)) => {
+                self.hasher.update(&buf[..n]);
+                self.len += n as u64;
+                Poll::Ready(Ok(n))
+            }
+            other => other,
+        }
+    }
+}
+
+impl fmt::Debug for ChecksumReader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ChecksumReader")
+            .field("inner", &self.inner)
+            .field("crc32", &self.finalize())
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl tokio::io::AsyncRead for ChecksumReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut tokio::io::ReadBuf,
+    ) -> Poll<io::Result<()>> {
+        let start = buf.filled().len();
+        let n = std::task::ready!(Pin::new(&mut self.inner).poll_read_buf(cx, buf, usize::MAX))?;
+        self.hasher.update(&buf.filled()[start..]);
+        self.len += n as u64;
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature = "futures")]
+impl futures::io::AsyncRead for ChecksumReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_read_checksummed(cx, buf)
+    }
+}
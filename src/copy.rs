@@ -0,0 +1,90 @@
+use crate::state::{lock, register_waker};
+use crate::{PipeReader, PipeWriter};
+use std::io;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+/// Copies everything `src` reads into `dst` until EOF, returning the number of bytes copied.
+///
+/// Unlike `tokio::io::copy`, the bytes are moved from one pipe's buffer into the other's without
+/// an intermediate buffer. Fails with `InvalidInput` if both halves belong to the same pipe, as
+/// the copy would never see EOF.
+pub async fn copy(src: &mut PipeReader, dst: &mut PipeWriter) -> io::Result<u64> {
+    if Arc::ptr_eq(&src.state, &dst.state) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{}: copy: Both halves belong to the same pipe",
+                env!("CARGO_PKG_NAME")
+            ),
+        ));
+    }
+
+    let mut copied = 0;
+    std::future::poll_fn(|cx| poll_copy(src, dst, cx, &mut copied)).await
+}
+
+fn poll_copy(
+    src: &PipeReader,
+    dst: &PipeWriter,
+    cx: &mut Context,
+    copied: &mut u64,
+) -> Poll<io::Result<u64>> {
+    // Always lock the two states in the same order, so copies running in opposite directions
+    // between the same pipes can't deadlock.
+    let (mut src_state, mut dst_state) = if Arc::as_ptr(&src.state) < Arc::as_ptr(&dst.state) {
+        let src_state = lock(&src.state);
+        (src_state, lock(&dst.state))
+    } else {
+        let dst_state = lock(&dst.state);
+        (lock(&src.state), dst_state)
+    };
+
+    loop {
+        if dst_state.write_closed(dst.id) {
+            return Poll::Ready(Err(dst_state.closed_error(dst.id)));
+        }
+
+        let unread = src_state.unread_len(src.id);
+        if unread == 0 {
+            if src_state.closed {
+                return Poll::Ready(Ok(*copied));
+            }
+            if src_state.read_wakes_writers() {
+                src_state.wake_writers();
+            }
+            register_waker(&mut src_state.reader_mut(src.id).waker, cx.waker());
+            return Poll::Pending;
+        }
+
+        let free = dst_state
+            .write_limit()
+            .saturating_sub(dst_state.buffer.len());
+        if free == 0 {
+            dst_state.wake_readers();
+            register_waker(&mut dst_state.writer_mut(dst.id).waker, cx.waker());
+            return Poll::Pending;
+        }
+
+        let n = unread.min(free);
+        let mut left = n;
+        for slice in src_state.unread_slices(src.id) {
+            let len = slice.len().min(left);
+            dst_state.buffer.extend_from_slice(&slice[..len]);
+            left -= len;
+            if left == 0 {
+                break;
+            }
+        }
+        src_state.advance_reader(src.id, n);
+        dst_state.record_write(n);
+        *copied += n as u64;
+
+        if src_state.read_wakes_writers() {
+            src_state.wake_writers();
+        }
+        if dst_state.write_wakes_readers() {
+            dst_state.wake_readers();
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use crate::state::lock;
+use crate::{pipe, PipeError, PipeReader, PipeWriter};
+use std::error::Error;
+use std::fmt;
+#[cfg(any(feature = "tokio", feature = "futures"))]
+use std::io;
+#[cfg(any(feature = "tokio", feature = "futures"))]
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+#[cfg(any(feature = "tokio", feature = "futures"))]
+use std::task::{Context, Poll};
+
+static NEXT_DUPLEX_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// One end of a bidirectional pipe created by [`duplex`](fn.duplex.html).
+///
+/// Bytes written to one end are read from the other one. Implements both `AsyncRead` and
+/// `AsyncWrite` for the enabled features, like [`PipeReader`](struct.PipeReader.html) and
+/// [`PipeWriter`](struct.PipeWriter.html) do.
+#[derive(Debug)]
+pub struct DuplexPipe {
+    writer: PipeWriter,
+    reader: PipeReader,
+}
+
+/// Error returned by [`DuplexPipe::unsplit`](struct.DuplexPipe.html#method.unsplit) when the two
+/// halves don't make up one end of a duplex pipe. It hands both halves back.
+pub struct UnsplitError(pub PipeWriter, pub PipeReader);
+
+/// Creates a pair of connected [`DuplexPipe`](struct.DuplexPipe.html)s, each backed by two
+/// independent pipes, one per direction.
+///
+/// Closing or dropping one end makes reads on the other end hit EOF and writes fail with
+/// `BrokenPipe`.
+pub fn duplex() -> (DuplexPipe, DuplexPipe) {
+    let (a_writer, b_reader) = pipe();
+    let (b_writer, a_reader) = pipe();
+    let id = NEXT_DUPLEX_ID.fetch_add(1, Ordering::Relaxed);
+    lock(&a_writer.state).duplex_id = Some(id);
+    lock(&b_writer.state).duplex_id = Some(id);
+
+    let a = DuplexPipe {
+        writer: a_writer,
+        reader: a_reader,
+    };
+    let b = DuplexPipe {
+        writer: b_writer,
+        reader: b_reader,
+    };
+
+    (a, b)
+}
+
+impl DuplexPipe {
+    /// Closes both directions, the peer reads EOF and its writes raise an error.
+    pub fn close(&self) -> Result<(), PipeError> {
+        self.writer.close()?;
+        self.reader.close()
+    }
+
+    /// Splits the end into the writer of one direction and the reader of the other one.
+    pub fn split(self) -> (PipeWriter, PipeReader) {
+        (self.writer, self.reader)
+    }
+
+    /// Recombines the halves returned by [`split`](#method.split).
+    ///
+    /// Fails unless the two halves make up one end of the same [`duplex`](fn.duplex.html) pipe.
+    pub fn unsplit(writer: PipeWriter, reader: PipeReader) -> Result<DuplexPipe, UnsplitError> {
+        let same_end = !Arc::ptr_eq(&writer.state, &reader.state) && {
+            let writer_id = lock(&writer.state).duplex_id;
+            writer_id.is_some() && writer_id == lock(&reader.state).duplex_id
+        };
+
+        if same_end {
+            Ok(DuplexPipe { writer, reader })
+        } else {
+            Err(UnsplitError(writer, reader))
+        }
+    }
+}
+
+impl fmt::Debug for UnsplitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("UnsplitError(..)")
+    }
+}
+
+impl fmt::Display for UnsplitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: tried to unsplit halves that are not one end of the same duplex pipe",
+            env!("CARGO_PKG_NAME")
+        )
+    }
+}
+
+impl Error for UnsplitError {}
+
+#[cfg(feature = "tokio")]
+impl tokio::io::AsyncRead for DuplexPipe {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut tokio::io::ReadBuf,
+    ) -> Poll<io::Result<()>> {
+        tokio::io::AsyncRead::poll_read(Pin::new(&mut self.reader), cx, buf)
+    }
+}
+
+#[cfg(feature = "futures")]
+impl futures::io::AsyncRead for DuplexPipe {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.reader).poll_read_impl(cx, buf)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl tokio::io::AsyncWrite for DuplexPipe {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.writer).poll_write_impl(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        bufs: &[io::IoSlice],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.writer).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        true
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.writer).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.writer).poll_shutdown(cx)
+    }
+}
+
+#[cfg(feature = "futures")]
+impl futures::io::AsyncWrite for DuplexPipe {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.writer).poll_write_impl(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        bufs: &[io::IoSlice],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.writer).poll_write_vectored(cx, bufs)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.writer).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.writer).poll_shutdown(cx)
+    }
+}
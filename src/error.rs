@@ -0,0 +1,108 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// The errors reported by the pipe itself, as opposed to the IO traits which report `io::Error`.
+///
+/// Converts into an `io::Error`, so it can be used with `?` in functions returning `io::Result`.
+/// A failed write holds it inside a [`ClosedError`](struct.ClosedError.html) instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PipeError {
+    /// A thread panicked while holding the lock on the state shared by both halves.
+    ///
+    /// Never returned anymore, a poisoned lock is recovered from instead. Kept so that matches on
+    /// `PipeError` keep compiling.
+    #[deprecated(note = "a poisoned lock is recovered from, this error is never returned")]
+    Poisoned,
+    /// The pipe is closed, writes fail with an `io::Error` of the contained kind.
+    Closed(io::ErrorKind),
+}
+
+impl fmt::Display for PipeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            #[allow(deprecated)]
+            PipeError::Poisoned => write!(
+                f,
+                "{}: Failed to lock the channel state: the lock is poisoned",
+                env!("CARGO_PKG_NAME")
+            ),
+            PipeError::Closed(_) => write!(f, "{}: The channel is closed", env!("CARGO_PKG_NAME")),
+        }
+    }
+}
+
+impl Error for PipeError {}
+
+impl PipeError {
+    // The kind of the `io::Error` it converts into.
+    fn kind(self) -> io::ErrorKind {
+        match self {
+            #[allow(deprecated)]
+            PipeError::Poisoned => io::ErrorKind::Other,
+            PipeError::Closed(kind) => kind,
+        }
+    }
+}
+
+impl From<PipeError> for io::Error {
+    fn from(err: PipeError) -> io::Error {
+        io::Error::new(err.kind(), err)
+    }
+}
+
+/// The error inside the `io::Error` of a write into a closed pipe.
+///
+/// Reach it with `io_error.get_ref().and_then(|err| err.downcast_ref::<ClosedError>())`, whether
+/// the pipe has a name or not. The message names the pipe if it has a name, the `PipeError` is
+/// returned by [`pipe_error`](#method.pipe_error) and by `Error::source`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClosedError {
+    error: PipeError,
+    name: Option<String>,
+}
+
+impl ClosedError {
+    pub(crate) fn new(kind: io::ErrorKind, name: Option<String>) -> ClosedError {
+        ClosedError {
+            error: PipeError::Closed(kind),
+            name,
+        }
+    }
+
+    /// Returns the `PipeError` to match on, always `PipeError::Closed`.
+    pub fn pipe_error(&self) -> PipeError {
+        self.error
+    }
+
+    /// Returns the name of the pipe, if one was set through `PipeBuilder::name`.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+}
+
+impl fmt::Display for ClosedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(
+                f,
+                "{}: PipeWriter({}): The channel is closed",
+                env!("CARGO_PKG_NAME"),
+                name
+            ),
+            None => self.error.fmt(f),
+        }
+    }
+}
+
+impl Error for ClosedError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<ClosedError> for io::Error {
+    fn from(err: ClosedError) -> io::Error {
+        io::Error::new(err.error.kind(), err)
+    }
+}
@@ -0,0 +1,128 @@
+use crate::{PipeReader, PipeWriter};
+use std::convert::{TryFrom, TryInto};
+use std::future::poll_fn;
+use std::io;
+use std::pin::Pin;
+
+const PREFIX_LEN: usize = 4;
+
+/// A [`PipeWriter`](struct.PipeWriter.html) sending length delimited frames.
+///
+/// Created by [`PipeWriter::framed`](struct.PipeWriter.html#method.framed). Every frame is
+/// written as its length, a big-endian `u32`, followed by its bytes. Read them back with a
+/// [`FramedReader`](struct.FramedReader.html).
+#[derive(Debug)]
+pub struct FramedWriter {
+    inner: PipeWriter,
+}
+
+/// A [`PipeReader`](struct.PipeReader.html) receiving the frames of a
+/// [`FramedWriter`](struct.FramedWriter.html).
+///
+/// Created by [`PipeReader::framed`](struct.PipeReader.html#method.framed).
+#[derive(Debug)]
+pub struct FramedReader {
+    inner: PipeReader,
+    // The bytes read from the pipe that don't make up a complete frame yet.
+    buf: Vec<u8>,
+}
+
+impl PipeWriter {
+    /// Sends length delimited frames instead of a stream of bytes.
+    pub fn framed(self) -> FramedWriter {
+        FramedWriter { inner: self }
+    }
+}
+
+impl PipeReader {
+    /// Receives length delimited frames instead of a stream of bytes.
+    pub fn framed(self) -> FramedReader {
+        FramedReader {
+            inner: self,
+            buf: Vec::new(),
+        }
+    }
+}
+
+impl FramedWriter {
+    /// Writes `frame` with its length prefix.
+    ///
+    /// Fails with an error of kind `InvalidInput` if the frame is longer than `u32::MAX` bytes. If
+    /// the future is dropped before it completes, a part of the frame may have been written
+    /// already and the stream can't be read back reliably.
+    pub async fn send(&mut self, frame: &[u8]) -> io::Result<()> {
+        let len = u32::try_from(frame.len()).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{}: The frame is longer than u32::MAX bytes",
+                    env!("CARGO_PKG_NAME")
+                ),
+            )
+        })?;
+
+        self.write_all(&len.to_be_bytes()).await?;
+        self.write_all(frame).await
+    }
+
+    /// Returns the writer back.
+    pub fn into_inner(self) -> PipeWriter {
+        self.inner
+    }
+
+    async fn write_all(&mut self, mut buf: &[u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            let n = poll_fn(|cx| Pin::new(&mut self.inner).poll_write_impl(cx, buf)).await?;
+            buf = &buf[n..];
+        }
+        Ok(())
+    }
+}
+
+impl FramedReader {
+    /// Reads the next complete frame, or `None` once the writers are gone.
+    ///
+    /// Fails with an error of kind `UnexpectedEof` if the pipe ends in the middle of a frame.
+    /// Dropping the future loses nothing, the bytes read so far are kept for the next call.
+    pub async fn next_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            if let Some(frame) = self.take_frame() {
+                return Ok(Some(frame));
+            }
+
+            let mut chunk = [0; crate::BUFFER_SIZE];
+            let n = poll_fn(|cx| Pin::new(&mut self.inner).poll_read_impl(cx, &mut chunk)).await?;
+            self.buf.extend_from_slice(&chunk[..n]);
+
+            if n == 0 {
+                if self.buf.is_empty() {
+                    return Ok(None);
+                }
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!(
+                        "{}: The pipe ended in the middle of a frame",
+                        env!("CARGO_PKG_NAME")
+                    ),
+                ));
+            }
+        }
+    }
+
+    /// Returns the reader back. The bytes of an incomplete frame read so far are lost.
+    pub fn into_inner(self) -> PipeReader {
+        self.inner
+    }
+
+    fn take_frame(&mut self) -> Option<Vec<u8>> {
+        let prefix = self.buf.get(..PREFIX_LEN)?;
+        let len = u32::from_be_bytes(prefix.try_into().unwrap()) as usize;
+        if self.buf.len() < PREFIX_LEN + len {
+            return None;
+        }
+
+        let frame = self.buf[PREFIX_LEN..PREFIX_LEN + len].to_vec();
+        self.buf.drain(..PREFIX_LEN + len);
+        Some(frame)
+    }
+}
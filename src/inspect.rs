@@ -0,0 +1,82 @@
+use crate::PipeReader;
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// A [`PipeReader`](struct.PipeReader.html) calling a closure with the bytes of every read.
+///
+/// Created by [`PipeReader::inspect`](struct.PipeReader.html#method.inspect). The closure sees
+/// each chunk right before the read returns it, which is enough to compute checksums or count
+/// bytes without a separate sink like [`tee`](struct.PipeReader.html#method.tee) needs.
+pub struct InspectReader<F> {
+    inner: PipeReader,
+    f: F,
+}
+
+impl PipeReader {
+    /// Calls `f` with the bytes returned by every successful read.
+    pub fn inspect<F: FnMut(&[u8])>(self, f: F) -> InspectReader<F> {
+        InspectReader { inner: self, f }
+    }
+}
+
+impl<F> InspectReader<F> {
+    /// Returns the reader back.
+    pub fn into_inner(self) -> PipeReader {
+        self.inner
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<F: FnMut(&[u8])> InspectReader<F> {
+    fn poll_read_inspected(&mut self, cx: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        match Pin::new(&mut self.inner).poll_read_impl(cx, buf) {
+            Poll::Ready(Ok(n)) => {
+                if n > 0 {
+                    (self.f)(&buf[..n]);
+                }
+                Poll::Ready(Ok(n))
+            }
+            other => other,
+        }
+    }
+}
+
+// The closure is never pinned, it is only called through `&mut`.
+impl<F> Unpin for InspectReader<F> {}
+
+impl<F> fmt::Debug for InspectReader<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("InspectReader")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<F: FnMut(&[u8])> tokio::io::AsyncRead for InspectReader<F> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut tokio::io::ReadBuf,
+    ) -> Poll<io::Result<()>> {
+        let start = buf.filled().len();
+        let n = std::task::ready!(Pin::new(&mut self.inner).poll_read_buf(cx, buf, usize::MAX))?;
+        if n > 0 {
+            (self.f)(&buf.filled()[start..]);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<F: FnMut(&[u8])> futures::io::AsyncRead for InspectReader<F> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_read_inspected(cx, buf)
+    }
+}
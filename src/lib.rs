@@ -3,9 +3,10 @@
 //! # Examples
 //!
 //! ```
+//! # #[cfg(feature = "tokio")]
 //! # async fn run() {
 //! use async_pipe;
-//! use tokio::prelude::*;
+//! use tokio::io::{AsyncReadExt, AsyncWriteExt};
 //!
 //! let (mut w, mut r) = async_pipe::pipe();
 //!  
@@ -19,49 +20,231 @@
 //! println!("Received: {:?}", String::from_utf8(v));
 //! # }
 //!
+//! # #[cfg(feature = "tokio")]
+//! tokio::runtime::Runtime::new().unwrap().block_on(run());
+//! ```
+//!
+//! # async-std and smol
+//!
+//! The `futures::io` traits are the ones `async-std` and `smol` use. Enable feature `async-std`,
+//! which is an alias of `futures`, and turn off the default features to not depend on tokio:
+//!
+//! ```toml
+//! [dependencies]
+//! async-pipe = { version = "0.1", default-features = false, features = ["async-std"] }
+//! ```
+//!
+//! # WebAssembly
+//!
+//! With the same features the crate builds for `wasm32-unknown-unknown`, the time based adapters
+//! then use the clock and timers of the browser.
+//!
+//! # no_std
+//!
+//! The crate needs `std`, and is meant to keep needing it. Both halves exist to implement the IO
+//! traits of tokio and futures, which are only defined with `std`, and its API reports
+//! `io::Error` and takes an `io::ErrorKind` in `PipeReader::close_with` and `PipeError::Closed`.
+//! Without `std` it would need a second API without those traits, with its own error type and
+//! lock, rather than a feature flag.
+//!
+//! # tokio and futures together
+//!
+//! Both features can be enabled at once, the halves then implement the traits of both crates.
+//! When the extension traits of both are in scope their methods share names, so call them fully
+//! qualified:
+//!
+//! ```
+//! # #[cfg(all(feature = "tokio", feature = "futures"))]
+//! # async fn run() {
+//! let (mut w, mut r) = async_pipe::pipe();
+//!
+//! tokio::io::AsyncWriteExt::write_all(&mut w, b"hello ").await.unwrap();
+//! futures::io::AsyncWriteExt::write_all(&mut w, b"world").await.unwrap();
+//! drop(w);
+//!
+//! let mut hello = [0; 6];
+//! tokio::io::AsyncReadExt::read_exact(&mut r, &mut hello).await.unwrap();
+//! let mut world = Vec::new();
+//! futures::io::AsyncReadExt::read_to_end(&mut r, &mut world).await.unwrap();
+//! assert_eq!(&hello, b"hello ");
+//! assert_eq!(world, b"world");
+//! # }
+//!
+//! # #[cfg(all(feature = "tokio", feature = "futures"))]
 //! tokio::runtime::Runtime::new().unwrap().block_on(run());
 //! ```
 //!
 //! # Featues
 //!
-//! * `tokio` (default) Implement `AsyncWrite` and `AsyncRead` from `tokio::io`.
-//! * `futures` Implement `AsyncWrite` and `AsyncRead` from `futures::io`
+//! * `tokio` (default) Implement `AsyncWrite`, `AsyncRead` and `AsyncBufRead` from `tokio::io`
+//! * `futures` Implement `AsyncWrite`, `AsyncRead` and `AsyncBufRead` from `futures::io`
+//! * `async-std` The same as `futures`
+//! * `bytes` Add `PipeWriter::write_bytes` to hand over `bytes::Bytes` chunks without copying,
+//!   `PipeReader::read_bytes` to take them out again, `PipeWriter::write_all_buf` to write out a
+//!   `bytes::Buf` and `PipeReader::read_buf` to read into a `bytes::BufMut`.
+//!   Together with `futures`, also add `PipeReader::into_stream` and implement `Sink<Bytes>` for
+//!   `PipeWriter`
+//! * `parking_lot` Lock the state shared by both halves with `parking_lot::Mutex`, which is never
+//!   poisoned
+//! * `crc32fast` Add `PipeReader::with_checksum` to compute the CRC32 of the bytes read
+//! * `framed` Add `PipeWriter::framed` and `PipeReader::framed` to send length delimited frames
+//! * `tokio-util` Add `PipeBuilder::with_cancellation` to tie the pipe to a `CancellationToken`
+//! * `tracing` Emit `tracing` events when reading, writing, closing and shutting down, inside a
+//!   span per pipe
+//!
+//! With `tokio` or `futures`, `PipeWriter::throttle` limits the throughput of a writer and
+//! `PipeReader::read_timeout` gives up on a read that takes too long. Both use the timer of tokio
+//! if feature `tokio` is enabled, which needs a tokio runtime, otherwise the one of
+//! `futures-timer`, which works with any executor.
 
-use state::State;
-use std::sync::{Arc, Mutex};
+use state::{Mutex, State, BUFFER_SIZE};
+use std::sync::Arc;
 
-pub use self::reader::PipeReader;
+pub use self::blocking::{BlockingReader, BlockingWriter};
+pub use self::builder::{CloseStats, PipeBuilder};
+#[cfg(any(feature = "tokio", feature = "futures"))]
+pub use self::chain::ChainReader;
+#[cfg(feature = "crc32fast")]
+pub use self::checksum::ChecksumReader;
+pub use self::copy::copy;
+pub use self::duplex::{duplex, DuplexPipe, UnsplitError};
+pub use self::error::{ClosedError, PipeError};
+#[cfg(feature = "framed")]
+pub use self::framed::{FramedReader, FramedWriter};
+#[cfg(any(feature = "tokio", feature = "futures"))]
+pub use self::inspect::InspectReader;
+pub use self::reader::{EofReason, PipeReader};
+pub use self::reunite::{Pipe, ReuniteError};
+pub use self::stats::PipeStats;
+#[cfg(all(feature = "futures", feature = "bytes"))]
+pub use self::stream::PipeStream;
+#[cfg(any(feature = "tokio", feature = "futures"))]
+pub use self::take::TakeReader;
+#[cfg(any(feature = "tokio", feature = "futures"))]
+pub use self::tee::TeeReader;
+#[cfg(any(feature = "tokio", feature = "futures"))]
+pub use self::throttle::ThrottledWriter;
+pub use self::weak::WeakPipeWriter;
 pub use self::writer::PipeWriter;
 
+// Emits a trace event inside the span of the pipe `state` belongs to, with feature `tracing`.
+macro_rules! trace {
+    ($state:expr, $($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(parent: &$state.span, $($arg)+);
+    };
+}
+
+mod blocking;
+mod buffer;
+mod builder;
+#[cfg(any(feature = "tokio", feature = "futures"))]
+mod chain;
+#[cfg(feature = "crc32fast")]
+mod checksum;
+mod copy;
+mod duplex;
+mod error;
+#[cfg(feature = "framed")]
+mod framed;
+#[cfg(any(feature = "tokio", feature = "futures"))]
+mod inspect;
+mod rate;
 mod reader;
+mod reunite;
 mod state;
+mod stats;
+#[cfg(all(feature = "futures", feature = "bytes"))]
+mod stream;
+#[cfg(any(feature = "tokio", feature = "futures"))]
+mod take;
+#[cfg(any(feature = "tokio", feature = "futures"))]
+mod tee;
+#[cfg(any(feature = "tokio", feature = "futures"))]
+mod throttle;
+#[cfg(any(feature = "tokio", feature = "futures"))]
+mod time;
+mod weak;
 mod writer;
 
-/// Creates a piped pair of an [`AsyncWrite`](https://docs.rs/tokio/0.2.16/tokio/io/trait.AsyncWrite.html) and an [`AsyncRead`](https://docs.rs/tokio/0.2.15/tokio/io/trait.AsyncRead.html).
+/// Creates a piped pair of an [`AsyncWrite`](https://docs.rs/tokio/1/tokio/io/trait.AsyncWrite.html) and an [`AsyncRead`](https://docs.rs/tokio/1/tokio/io/trait.AsyncRead.html).
+///
+/// Use [`PipeBuilder`](struct.PipeBuilder.html) to configure the pipe.
 pub fn pipe() -> (PipeWriter, PipeReader) {
-    let shared_state = Arc::new(Mutex::new(State {
-        reader_waker: None,
-        writer_waker: None,
-        closed: false,
-        buffer: Vec::new(),
-    }));
-
-    let w = PipeWriter {
-        state: Arc::clone(&shared_state),
-    };
+    PipeBuilder::default().build()
+}
 
-    let r = PipeReader {
-        state: Arc::clone(&shared_state),
-    };
+/// Creates a piped pair like [`pipe`](fn.pipe.html), buffering up to `cap` bytes between the two halves.
+///
+/// The writer can make progress until `cap` bytes are waiting to be read, only then does it wait
+/// for the reader.
+///
+/// # Panics
+///
+/// Panics if `cap` is zero.
+pub fn pipe_with_capacity(cap: usize) -> (PipeWriter, PipeReader) {
+    PipeBuilder::new().capacity(cap).build()
+}
 
-    (w, r)
+/// Creates a piped pair like [`pipe`](fn.pipe.html), with `initial` already buffered for the reader.
+///
+/// The reader reads the preloaded bytes first, then anything written afterwards. The preloaded
+/// bytes count against the capacity of the pipe until they are read, even if there are more of
+/// them than fit into it.
+pub fn pipe_from(initial: impl Into<Vec<u8>>) -> (PipeWriter, PipeReader) {
+    PipeBuilder::new().initial(initial).build()
 }
 
-#[cfg(test)]
+/// Creates a writer whose data is read by each of the `n` returned readers.
+///
+/// Every reader receives the whole stream independently and sees EOF once the writer is dropped
+/// or shut down. Dropping a reader doesn't affect the others, the writer only fails with
+/// `BrokenPipe` once all of them are gone.
+///
+/// The writer is only as fast as the slowest reader: a written chunk is retained, and counts
+/// against the capacity of the pipe, until every reader has consumed it.
+///
+/// With `n` zero there is nobody to read, so the pipe is closed from the start and the writer
+/// fails with `BrokenPipe`, as if every reader was already dropped.
+pub fn broadcast(n: usize) -> (PipeWriter, Vec<PipeReader>) {
+    let mut state = State::new(BUFFER_SIZE);
+    if n == 0 {
+        state.mark_closed(EofReason::Aborted);
+    }
+    let shared_state = Arc::new(Mutex::new(state));
+
+    let w = PipeWriter::new(Arc::clone(&shared_state));
+
+    let readers = (0..n)
+        .map(|_| PipeReader::new(Arc::clone(&shared_state)))
+        .collect();
+
+    (w, readers)
+}
+
+#[cfg(all(test, feature = "tokio"))]
 mod test {
-    use super::pipe;
-    use std::io;
-    use tokio::prelude::*;
+    use super::{
+        broadcast, copy, duplex, pipe, pipe_from, pipe_with_capacity, CloseStats, EofReason,
+        PipeBuilder, PipeStats,
+    };
+    use std::io::{self, IoSlice};
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::Wake;
+    use std::time::Duration;
+    use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+
+    /// A waker counting how often it is woken.
+    #[derive(Default)]
+    struct CountWakes(AtomicUsize);
+
+    impl Wake for CountWakes {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
 
     #[tokio::test]
     async fn read_write() {
@@ -88,6 +271,25 @@ mod test {
         assert_eq!(bytes_read, 0);
     }
 
+    #[tokio::test]
+    async fn eof_wakes_pending_reader() {
+        let (writer, mut reader) = pipe();
+
+        let read = tokio::spawn(async move {
+            let mut buf = [0u8; 8];
+            reader.read(&mut buf).await.unwrap()
+        });
+        // Let the reader register its waker before the writer goes away.
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        drop(writer);
+
+        let bytes_read = tokio::time::timeout(Duration::from_secs(1), read)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(bytes_read, 0);
+    }
+
     #[tokio::test]
     async fn broken_pipe_when_reader_is_dropped() {
         let (mut writer, reader) = pipe();
@@ -111,7 +313,7 @@ mod test {
         const BUF_SIZE: usize = 8;
 
         let write_handle = tokio::spawn(async move {
-            writer.write_all(&mut [0u8; BUF_SIZE]).await.unwrap();
+            writer.write_all(&[0u8; BUF_SIZE]).await.unwrap();
         });
 
         let mut buf = [0u8; BUF_SIZE];
@@ -119,4 +321,2960 @@ mod test {
         drop(reader);
         write_handle.await.unwrap();
     }
+
+    #[tokio::test]
+    async fn write_up_to_capacity_without_reader() {
+        let (mut writer, reader) = pipe_with_capacity(16);
+
+        writer.write_all(&[0u8; 16]).await.unwrap();
+        assert!(!reader.is_flushed().unwrap());
+
+        let blocked =
+            tokio::time::timeout(Duration::from_millis(10), writer.write(&[0u8; 1])).await;
+        assert!(blocked.is_err());
+    }
+
+    #[tokio::test]
+    async fn partial_write_when_nearly_full() {
+        let (mut writer, mut reader) = pipe_with_capacity(4);
+
+        let bytes_written = writer.write(b"hello").await.unwrap();
+        assert_eq!(bytes_written, 4);
+
+        let mut buf = [0u8; 8];
+        let bytes_read = reader.read(&mut buf).await.unwrap();
+        assert_eq!(&buf[..bytes_read], b"hell");
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_capacity_panics() {
+        let _ = pipe_with_capacity(0);
+    }
+
+    #[tokio::test]
+    async fn read_large_write_in_small_chunks() {
+        let (mut writer, mut reader) = pipe();
+        let data: Vec<u8> = (0..100).collect();
+        let expected = data.clone();
+
+        let write_handle = tokio::spawn(async move {
+            writer.write_all(&data).await.unwrap();
+        });
+
+        let mut read_buf = Vec::new();
+        let mut chunk = [0u8; 8];
+        loop {
+            let bytes_read = reader.read(&mut chunk).await.unwrap();
+            if bytes_read == 0 {
+                break;
+            }
+            assert!(bytes_read <= chunk.len());
+            read_buf.extend_from_slice(&chunk[..bytes_read]);
+        }
+        write_handle.await.unwrap();
+
+        assert_eq!(read_buf, expected);
+    }
+
+    #[tokio::test]
+    async fn read_into_slice() {
+        let (mut writer, mut reader) = pipe();
+        writer.write_all(b"hello").await.unwrap();
+
+        let mut buf = [0u8; 8];
+        let bytes_read = reader.read(&mut buf).await.unwrap();
+        assert_eq!(&buf[..bytes_read], b"hello");
+    }
+
+    #[tokio::test]
+    async fn read_buf_partial_fill() {
+        let (mut writer, mut reader) = pipe();
+        writer.write_all(b"hello world").await.unwrap();
+
+        let mut storage = [0u8; 5];
+        let mut buf = ReadBuf::new(&mut storage);
+        std::future::poll_fn(|cx| AsyncRead::poll_read(Pin::new(&mut reader), cx, &mut buf))
+            .await
+            .unwrap();
+        assert_eq!(buf.filled(), b"hello");
+        assert_eq!(buf.remaining(), 0);
+
+        drop(writer);
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).await.unwrap();
+        assert_eq!(&rest, b" world");
+    }
+
+    #[tokio::test]
+    async fn read_buf_appends_after_filled() {
+        let (mut writer, mut reader) = pipe();
+        writer.write_all(b"world").await.unwrap();
+
+        let mut storage = [0u8; 16];
+        let mut buf = ReadBuf::new(&mut storage);
+        buf.put_slice(b"hello ");
+        std::future::poll_fn(|cx| AsyncRead::poll_read(Pin::new(&mut reader), cx, &mut buf))
+            .await
+            .unwrap();
+        assert_eq!(buf.filled(), b"hello world");
+    }
+
+    #[tokio::test]
+    async fn read_line_and_read_until() {
+        let (mut writer, mut reader) = pipe();
+
+        let write_handle = tokio::spawn(async move {
+            writer.write_all(b"first line\nsecond;third").await.unwrap();
+        });
+
+        let mut line = String::new();
+        reader.read_line(&mut line).await.unwrap();
+        assert_eq!(line, "first line\n");
+
+        let mut record = Vec::new();
+        reader.read_until(b';', &mut record).await.unwrap();
+        assert_eq!(&record, b"second;");
+
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).await.unwrap();
+        assert_eq!(&rest, b"third");
+        write_handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn lines() {
+        let (mut writer, reader) = pipe();
+
+        let write_handle = tokio::spawn(async move {
+            for record in &["alpha", "beta", "gamma"] {
+                writer.write_all(record.as_bytes()).await.unwrap();
+                writer.write_all(b"\n").await.unwrap();
+            }
+        });
+
+        let mut lines = reader.lines();
+        let mut records = Vec::new();
+        while let Some(line) = lines.next_line().await.unwrap() {
+            records.push(line);
+        }
+        write_handle.await.unwrap();
+
+        assert_eq!(records, vec!["alpha", "beta", "gamma"]);
+    }
+
+    #[tokio::test]
+    async fn fill_buf_does_not_consume() {
+        let (mut writer, mut reader) = pipe();
+        writer.write_all(b"hello").await.unwrap();
+
+        assert_eq!(reader.fill_buf().await.unwrap(), b"hello");
+        assert_eq!(reader.fill_buf().await.unwrap(), b"hello");
+        reader.consume(2);
+        assert_eq!(reader.fill_buf().await.unwrap(), b"llo");
+
+        let mut buf = [0u8; 8];
+        let bytes_read = reader.read(&mut buf).await.unwrap();
+        assert_eq!(&buf[..bytes_read], b"llo");
+        assert!(reader.is_flushed().unwrap());
+    }
+
+    #[cfg(feature = "bytes")]
+    #[tokio::test]
+    async fn write_bytes_without_copy() {
+        let (mut writer, reader) = pipe();
+        let data = bytes::Bytes::from(vec![7u8; 1024 * 1024]);
+        let data_ptr = data.as_ptr();
+
+        writer.write_bytes(data).await.unwrap();
+
+        let state = crate::state::lock(&reader.state);
+        let chunk = state.buffer.slices().next().unwrap();
+        assert_eq!(chunk.len(), 1024 * 1024);
+        assert_eq!(chunk.as_ptr(), data_ptr);
+    }
+
+    #[cfg(feature = "bytes")]
+    #[tokio::test]
+    async fn read_bytes_without_copy() {
+        let (mut writer, mut reader) = pipe();
+        let data = bytes::Bytes::from(vec![7u8; 1024 * 1024]);
+        let data_ptr = data.as_ptr();
+
+        writer.write_bytes(data).await.unwrap();
+        let mut buf = [0u8; 2];
+        reader.read_exact(&mut buf).await.unwrap();
+        let chunk = reader.read_bytes().await.unwrap().unwrap();
+        assert_eq!(chunk.len(), 1024 * 1024 - 2);
+        assert_eq!(chunk.as_ptr(), data_ptr.wrapping_add(2));
+
+        writer.write_all(b"copied").await.unwrap();
+        reader.unread(b"back").unwrap();
+        drop(writer);
+        assert_eq!(reader.read_bytes().await.unwrap().unwrap(), "back");
+        assert_eq!(reader.read_bytes().await.unwrap().unwrap(), "copied");
+        assert_eq!(reader.read_bytes().await.unwrap(), None);
+    }
+
+    #[cfg(feature = "bytes")]
+    #[tokio::test]
+    async fn write_bytes_after_write() {
+        let (mut writer, mut reader) = pipe();
+
+        let write_handle = tokio::spawn(async move {
+            writer.write_all(b"hello ").await.unwrap();
+            writer
+                .write_bytes(bytes::Bytes::from_static(b"big "))
+                .await
+                .unwrap();
+            writer.write_all(b"world").await.unwrap();
+        });
+
+        let mut read_buf = Vec::new();
+        reader.read_to_end(&mut read_buf).await.unwrap();
+        write_handle.await.unwrap();
+
+        assert_eq!(&read_buf, b"hello big world");
+    }
+
+    #[tokio::test]
+    async fn write_vectored() {
+        let (mut writer, mut reader) = pipe();
+        assert!(writer.is_write_vectored());
+
+        let bufs = [
+            IoSlice::new(b"hello"),
+            IoSlice::new(b" "),
+            IoSlice::new(b"world"),
+        ];
+        let bytes_written = writer.write_vectored(&bufs).await.unwrap();
+        assert_eq!(bytes_written, 11);
+        drop(writer);
+
+        let mut read_buf = Vec::new();
+        reader.read_to_end(&mut read_buf).await.unwrap();
+        assert_eq!(&read_buf, b"hello world");
+    }
+
+    #[tokio::test]
+    async fn write_vectored_up_to_capacity() {
+        let (mut writer, mut reader) = pipe_with_capacity(8);
+
+        let bufs = [IoSlice::new(b"hello"), IoSlice::new(b"world")];
+        let bytes_written = writer.write_vectored(&bufs).await.unwrap();
+        assert_eq!(bytes_written, 8);
+
+        let mut buf = [0u8; 16];
+        let bytes_read = reader.read(&mut buf).await.unwrap();
+        assert_eq!(&buf[..bytes_read], b"hellowor");
+    }
+
+    #[tokio::test]
+    async fn broadcast_to_every_reader() {
+        let (mut writer, readers) = broadcast(3);
+        let data: Vec<u8> = (0..4096).map(|i| i as u8).collect();
+        let expected = data.clone();
+
+        let read_handles: Vec<_> = readers
+            .into_iter()
+            .map(|mut reader| {
+                tokio::spawn(async move {
+                    let mut read_buf = Vec::new();
+                    reader.read_to_end(&mut read_buf).await.unwrap();
+                    read_buf
+                })
+            })
+            .collect();
+
+        writer.write_all(&data).await.unwrap();
+        drop(writer);
+
+        for handle in read_handles {
+            assert_eq!(handle.await.unwrap(), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn broadcast_waits_for_slowest_reader() {
+        let (mut writer, mut readers) = broadcast(2);
+        let mut fast = readers.remove(0);
+        let mut slow = readers.remove(0);
+
+        writer.write_all(&[1u8; 1024]).await.unwrap();
+        let mut buf = [0u8; 1024];
+        fast.read_exact(&mut buf).await.unwrap();
+
+        let blocked = tokio::time::timeout(Duration::from_millis(10), writer.write(&[1u8])).await;
+        assert!(blocked.is_err());
+
+        slow.read_exact(&mut buf[..1]).await.unwrap();
+        assert_eq!(writer.write(&[2u8]).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn broadcast_survives_dropped_reader() {
+        let (mut writer, mut readers) = broadcast(2);
+        drop(readers.remove(0));
+        let mut reader = readers.remove(0);
+
+        writer.write_all(b"hello").await.unwrap();
+        drop(writer);
+
+        let mut read_buf = Vec::new();
+        reader.read_to_end(&mut read_buf).await.unwrap();
+        assert_eq!(&read_buf, b"hello");
+    }
+
+    #[tokio::test]
+    async fn broken_pipe_when_every_broadcast_reader_is_dropped() {
+        let (mut writer, readers) = broadcast(2);
+        drop(readers);
+        let io_error = writer.write_all(&[0u8; 8]).await.unwrap_err();
+        assert_eq!(io_error.kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[tokio::test]
+    async fn broadcast_to_no_reader() {
+        let (mut writer, readers) = broadcast(0);
+        assert!(readers.is_empty());
+        assert!(writer.is_closed().unwrap());
+        let io_error = writer.write_all(b"hello").await.unwrap_err();
+        assert_eq!(io_error.kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[tokio::test]
+    async fn eof_when_every_writer_clone_is_dropped() {
+        let (writer, mut reader) = pipe();
+        let mut other_writer = writer.clone();
+        drop(writer);
+
+        let write_handle = tokio::spawn(async move {
+            other_writer.write_all(b"still open").await.unwrap();
+        });
+
+        let mut read_buf = Vec::new();
+        reader.read_to_end(&mut read_buf).await.unwrap();
+        write_handle.await.unwrap();
+
+        assert_eq!(&read_buf, b"still open");
+    }
+
+    #[tokio::test]
+    async fn write_from_several_clones() {
+        let (writer, mut reader) = pipe_with_capacity(4);
+
+        let write_handles: Vec<_> = (0..4u8)
+            .map(|i| {
+                let mut writer = writer.clone();
+                tokio::spawn(async move {
+                    writer.write_all(&[i; 64]).await.unwrap();
+                })
+            })
+            .collect();
+        drop(writer);
+
+        let mut read_buf = Vec::new();
+        reader.read_to_end(&mut read_buf).await.unwrap();
+        for handle in write_handles {
+            handle.await.unwrap();
+        }
+
+        read_buf.sort_unstable();
+        let expected: Vec<u8> = (0..4u8).flat_map(|i| vec![i; 64]).collect();
+        assert_eq!(read_buf, expected);
+    }
+
+    #[tokio::test]
+    async fn peek_does_not_consume() {
+        let (mut writer, mut reader) = pipe();
+
+        let write_handle = tokio::spawn(async move {
+            writer.write_all(b"GET / HTTP/1.1").await.unwrap();
+        });
+
+        let mut peeked = [0u8; 4];
+        let bytes_peeked = reader.peek(&mut peeked).await.unwrap();
+        assert_eq!(&peeked[..bytes_peeked], b"GET ");
+
+        let mut read_buf = Vec::new();
+        reader.read_to_end(&mut read_buf).await.unwrap();
+        write_handle.await.unwrap();
+
+        assert_eq!(&read_buf, b"GET / HTTP/1.1");
+    }
+
+    #[tokio::test]
+    async fn peek_at_eof() {
+        let (writer, mut reader) = pipe();
+        drop(writer);
+        let mut buf = [0u8; 4];
+        assert_eq!(reader.peek(&mut buf).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn buffered_len_tracks_unread_bytes() {
+        let (mut writer, mut reader) = pipe();
+        assert_eq!(reader.buffered_len().unwrap(), 0);
+
+        writer.write_all(b"hello world").await.unwrap();
+        assert_eq!(reader.buffered_len().unwrap(), 11);
+
+        let mut buf = [0u8; 5];
+        reader.read_exact(&mut buf).await.unwrap();
+        assert_eq!(reader.buffered_len().unwrap(), 6);
+
+        let mut rest = [0u8; 6];
+        reader.read_exact(&mut rest).await.unwrap();
+        reader.close().unwrap();
+        assert_eq!(reader.buffered_len().unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn is_closed_after_close() {
+        let (writer, reader) = pipe();
+        assert!(!writer.is_closed().unwrap());
+        assert!(!reader.is_closed().unwrap());
+
+        reader.close().unwrap();
+        assert!(writer.is_closed().unwrap());
+        assert!(reader.is_closed().unwrap());
+    }
+
+    #[tokio::test]
+    async fn is_closed_after_reader_drop() {
+        let (mut writer, reader) = pipe();
+        drop(reader);
+        assert!(writer.is_closed().unwrap());
+
+        let io_error = writer.write(b"hello").await.unwrap_err();
+        assert_eq!(io_error.kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[tokio::test]
+    async fn is_closed_after_writer_shutdown() {
+        let (mut writer, reader) = pipe();
+        writer.shutdown().await.unwrap();
+        assert!(writer.is_closed().unwrap());
+        assert!(reader.is_closed().unwrap());
+    }
+
+    #[tokio::test]
+    async fn try_read_with_data() {
+        let (mut writer, mut reader) = pipe();
+        writer.write_all(b"hello").await.unwrap();
+
+        let mut buf = [0u8; 8];
+        let bytes_read = reader.try_read(&mut buf).unwrap();
+        assert_eq!(&buf[..bytes_read], b"hello");
+    }
+
+    #[test]
+    fn try_read_when_empty() {
+        let (_writer, mut reader) = pipe();
+        let mut buf = [0u8; 8];
+        let io_error = reader.try_read(&mut buf).unwrap_err();
+        assert_eq!(io_error.kind(), io::ErrorKind::WouldBlock);
+    }
+
+    #[test]
+    fn try_read_when_closed() {
+        let (writer, mut reader) = pipe();
+        drop(writer);
+        let mut buf = [0u8; 8];
+        assert_eq!(reader.try_read(&mut buf).unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn try_write_partially_then_full() {
+        let (mut writer, mut reader) = pipe_with_capacity(4);
+
+        assert_eq!(writer.try_write(b"hel").unwrap(), 3);
+        assert_eq!(writer.try_write(b"lo").unwrap(), 1);
+        let io_error = writer.try_write(b"o").unwrap_err();
+        assert_eq!(io_error.kind(), io::ErrorKind::WouldBlock);
+
+        let mut buf = [0u8; 8];
+        let bytes_read = reader.read(&mut buf).await.unwrap();
+        assert_eq!(&buf[..bytes_read], b"hell");
+        assert_eq!(writer.try_write(b"o").unwrap(), 1);
+    }
+
+    #[test]
+    fn try_write_when_reader_is_dropped() {
+        let (mut writer, reader) = pipe();
+        drop(reader);
+        let io_error = writer.try_write(b"hello").unwrap_err();
+        assert_eq!(io_error.kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn copy_through_blocking_adapters() {
+        let (writer, reader) = pipe_with_capacity(64);
+        let data: Vec<u8> = (0..4096).map(|i| i as u8).collect();
+        let expected = data.clone();
+
+        let write_handle = std::thread::spawn(move || {
+            let mut writer = writer.into_blocking();
+            std::io::copy(&mut data.as_slice(), &mut writer).unwrap();
+            std::io::Write::flush(&mut writer).unwrap();
+        });
+
+        let mut reader = reader.into_blocking();
+        let mut read_buf = Vec::new();
+        std::io::copy(&mut reader, &mut read_buf).unwrap();
+        write_handle.join().unwrap();
+
+        assert_eq!(read_buf, expected);
+    }
+
+    #[tokio::test]
+    async fn duplex_round_trip() {
+        let (mut client, mut server) = duplex();
+
+        let server_handle = tokio::spawn(async move {
+            let mut buf = [0u8; 4];
+            server.read_exact(&mut buf).await.unwrap();
+            server.write_all(&buf).await.unwrap();
+            server.write_all(b" pong").await.unwrap();
+        });
+
+        client.write_all(b"ping").await.unwrap();
+        let mut read_buf = Vec::new();
+        client.read_to_end(&mut read_buf).await.unwrap();
+        server_handle.await.unwrap();
+
+        assert_eq!(&read_buf, b"ping pong");
+    }
+
+    #[tokio::test]
+    async fn duplex_write_vectored() {
+        let (mut client, mut server) = duplex();
+        assert!(client.is_write_vectored());
+
+        let bufs = [IoSlice::new(b"hello "), IoSlice::new(b"world")];
+        assert_eq!(client.write_vectored(&bufs).await.unwrap(), 11);
+        let mut buf = [0u8; 11];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello world");
+    }
+
+    #[tokio::test]
+    async fn duplex_peer_sees_close() {
+        let (mut client, server) = duplex();
+        server.close().unwrap();
+
+        let mut buf = [0u8; 8];
+        assert_eq!(client.read(&mut buf).await.unwrap(), 0);
+        let io_error = client.write_all(b"hello").await.unwrap_err();
+        assert_eq!(io_error.kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[tokio::test]
+    async fn reunite_halves_of_the_same_pipe() {
+        let (writer, reader) = pipe();
+        let mut pipe = writer.reunite(reader).unwrap();
+
+        pipe.write_all(b"hello").await.unwrap();
+        let mut buf = [0u8; 5];
+        pipe.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+
+        let (mut writer, mut reader) = pipe.into_split();
+        writer.write_all(b"world").await.unwrap();
+        reader.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"world");
+    }
+
+    #[test]
+    fn reunite_mismatched_halves() {
+        let (writer, _) = pipe();
+        let (_, reader) = pipe();
+
+        let super::ReuniteError(writer, reader) = match writer.reunite(reader) {
+            Ok(_) => panic!("halves of different pipes were reunited"),
+            Err(err) => err,
+        };
+        assert!(!std::sync::Arc::ptr_eq(&writer.state, &reader.state));
+    }
+
+    #[tokio::test]
+    async fn into_inner_returns_unread_bytes() {
+        let (mut writer, mut reader) = pipe();
+        let data: Vec<u8> = (0..100).collect();
+        writer.write_all(&data).await.unwrap();
+
+        let mut buf = [0u8; 10];
+        reader.read_exact(&mut buf).await.unwrap();
+
+        assert_eq!(reader.into_inner().unwrap(), &data[10..]);
+    }
+
+    #[tokio::test]
+    async fn reset_after_close() {
+        let (mut writer, mut reader) = pipe();
+        let mut buf = [0u8; 5];
+
+        writer.write_all(b"first").await.unwrap();
+        reader.read_exact(&mut buf).await.unwrap();
+        writer.close().unwrap();
+        assert_eq!(reader.read(&mut buf).await.unwrap(), 0);
+
+        reader.reset().unwrap();
+        assert!(!writer.is_closed().unwrap());
+
+        writer.write_all(b"again").await.unwrap();
+        reader.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"again");
+    }
+
+    #[tokio::test]
+    async fn reset_with_unread_data() {
+        let (mut writer, reader) = pipe();
+        writer.write_all(b"unread").await.unwrap();
+        writer.close().unwrap();
+        assert!(writer.reset().is_err());
+        assert!(reader.is_closed().unwrap());
+    }
+
+    #[tokio::test]
+    async fn close_with_custom_error_kind() {
+        let (mut writer, reader) = pipe();
+        reader.close_with(io::ErrorKind::ConnectionAborted).unwrap();
+
+        let io_error = writer.write_all(b"hello").await.unwrap_err();
+        assert_eq!(io_error.kind(), io::ErrorKind::ConnectionAborted);
+        let io_error = writer.try_write(b"hello").unwrap_err();
+        assert_eq!(io_error.kind(), io::ErrorKind::ConnectionAborted);
+    }
+
+    #[cfg(not(feature = "parking_lot"))]
+    #[tokio::test]
+    async fn keep_working_after_poisoned_lock() {
+        use crate::{ClosedError, PipeError};
+
+        let (mut writer, mut reader) = pipe();
+
+        let state = std::sync::Arc::clone(&reader.state);
+        std::thread::spawn(move || {
+            let _guard = state.lock().unwrap();
+            panic!("poison the lock");
+        })
+        .join()
+        .unwrap_err();
+        assert!(reader.state.is_poisoned());
+
+        writer.write_all(b"hello").await.unwrap();
+        let mut buf = [0; 5];
+        reader.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+        assert!(reader.is_flushed().unwrap());
+
+        writer.close().unwrap();
+        let io_error = writer.write_all(b"hello").await.unwrap_err();
+        let closed_error = io_error.get_ref().unwrap().downcast_ref::<ClosedError>();
+        assert_eq!(
+            closed_error.map(ClosedError::pipe_error),
+            Some(PipeError::Closed(io::ErrorKind::BrokenPipe))
+        );
+    }
+
+    #[tokio::test]
+    async fn debug_reports_state() {
+        let (mut writer, reader) = pipe();
+        writer.write_all(b"hello").await.unwrap();
+
+        assert_eq!(
+            format!("{:?}", reader),
+            "PipeReader { closed: false, buffered: 5, waker: false }"
+        );
+
+        writer.close().unwrap();
+        assert_eq!(
+            format!("{:?}", writer),
+            "PipeWriter { closed: true, buffered: 5, waker: false }"
+        );
+    }
+
+    #[tokio::test]
+    async fn count_bytes_transferred() {
+        let (mut writer, mut reader) = pipe_with_capacity(4);
+        let messages: [&[u8]; 3] = [b"hello", b" ", b"world"];
+
+        let write = tokio::spawn(async move {
+            for message in &messages {
+                writer.write_all(message).await.unwrap();
+            }
+            writer.flush().await.unwrap();
+            writer.bytes_written().unwrap()
+        });
+
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"hello world");
+
+        assert_eq!(write.await.unwrap(), 11);
+        assert_eq!(reader.bytes_read().unwrap(), 11);
+    }
+
+    // Run with `cargo test --release -- --ignored --nocapture`, with and without the `parking_lot`
+    // feature, to compare the locking overhead.
+    #[tokio::test]
+    #[ignore]
+    async fn bench_small_round_trips() {
+        const ROUNDS: usize = 200_000;
+        let (mut writer, mut reader) = pipe_with_capacity(16);
+
+        let start = std::time::Instant::now();
+        let write = tokio::spawn(async move {
+            for _ in 0..ROUNDS {
+                writer.write_all(&[1; 16]).await.unwrap();
+            }
+        });
+
+        let mut buf = [0; 16];
+        for _ in 0..ROUNDS {
+            reader.read_exact(&mut buf).await.unwrap();
+        }
+        write.await.unwrap();
+
+        println!(
+            "{} round trips of 16 bytes in {:?}",
+            ROUNDS,
+            start.elapsed()
+        );
+    }
+
+    // Reads in smaller pieces than written, so every read leaves bytes behind in the buffer.
+    #[tokio::test]
+    #[ignore]
+    async fn bench_large_transfer() {
+        const LEN: usize = 16 * 1024 * 1024;
+        let (mut writer, mut reader) = pipe_with_capacity(64 * 1024);
+
+        let start = std::time::Instant::now();
+        let write = tokio::spawn(async move {
+            let chunk = vec![1; 8 * 1024];
+            for _ in 0..LEN / chunk.len() {
+                writer.write_all(&chunk).await.unwrap();
+            }
+        });
+
+        let mut buf = vec![0; 1024];
+        let mut read = 0;
+        loop {
+            match reader.read(&mut buf).await.unwrap() {
+                0 => break,
+                n => read += n,
+            }
+        }
+        write.await.unwrap();
+        assert_eq!(read, LEN);
+
+        println!("{} bytes transferred in {:?}", LEN, start.elapsed());
+    }
+
+    #[tokio::test]
+    async fn read_preloaded_then_written() {
+        let (mut writer, mut reader) = pipe_from(&b"hello"[..]);
+
+        let mut buf = [0; 5];
+        reader.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+
+        writer.write_all(b" world").await.unwrap();
+        drop(writer);
+
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b" world");
+    }
+
+    #[cfg(all(feature = "futures", feature = "bytes"))]
+    #[tokio::test]
+    async fn read_as_stream() {
+        use futures::stream::TryStreamExt;
+
+        let (mut writer, reader) = pipe_with_capacity(4);
+
+        let write = tokio::spawn(async move {
+            writer.write_all(b"hello world").await.unwrap();
+        });
+
+        let chunks: Vec<bytes::Bytes> = reader.into_stream().try_collect().await.unwrap();
+        assert!(chunks.iter().all(|chunk| chunk.len() <= 4));
+        assert_eq!(chunks.concat(), b"hello world");
+        write.await.unwrap();
+    }
+
+    #[cfg(all(feature = "futures", feature = "bytes"))]
+    #[tokio::test]
+    async fn stream_chunks_without_copy() {
+        use futures::stream::TryStreamExt;
+
+        let (mut writer, reader) = pipe();
+        let data = bytes::Bytes::from(vec![7u8; 1024 * 1024]);
+        let data_ptr = data.as_ptr();
+
+        writer.write_bytes(data).await.unwrap();
+        drop(writer);
+
+        let chunks: Vec<bytes::Bytes> = reader.into_stream().try_collect().await.unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].len(), 1024 * 1024);
+        assert_eq!(chunks[0].as_ptr(), data_ptr);
+    }
+
+    #[cfg(all(feature = "futures", feature = "bytes"))]
+    #[tokio::test]
+    async fn forward_stream_into_sink() {
+        use futures::stream::{self, StreamExt};
+
+        let (writer, mut reader) = pipe_with_capacity(4);
+        let chunks = vec!["hello", " ", "world"]
+            .into_iter()
+            .map(|chunk| Ok(bytes::Bytes::from_static(chunk.as_bytes())));
+
+        let write = tokio::spawn(stream::iter(chunks).forward(writer));
+
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"hello world");
+        write.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn flush_waits_for_reader() {
+        let (mut writer, mut reader) = pipe();
+        let (read_tx, mut read_rx) = tokio::sync::oneshot::channel();
+
+        let read = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            let mut buf = [0; 5];
+            reader.read_exact(&mut buf).await.unwrap();
+            read_tx.send(()).unwrap();
+            reader
+        });
+
+        writer.write_all(b"hello").await.unwrap();
+        writer.flush().await.unwrap();
+        assert!(read_rx.try_recv().is_ok());
+        read.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn closed_resolves_when_reader_is_dropped() {
+        let (writer, reader) = pipe();
+
+        let closed = tokio::spawn(async move { writer.closed().await });
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            drop(reader);
+        });
+
+        tokio::time::timeout(Duration::from_secs(1), closed)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn closed_resolves_when_clone_closes() {
+        let (writer, _reader) = pipe();
+        let clone = writer.clone();
+
+        let closed = tokio::spawn(async move { clone.closed().await });
+        tokio::task::yield_now().await;
+        writer.close().unwrap();
+
+        tokio::time::timeout(Duration::from_secs(1), closed)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn zero_length_read() {
+        let (mut writer, mut reader) = pipe();
+        assert_eq!(reader.read(&mut []).await.unwrap(), 0);
+
+        writer.write_all(b"hello").await.unwrap();
+        assert_eq!(reader.read(&mut []).await.unwrap(), 0);
+        assert_eq!(reader.buffered_len().unwrap(), 5);
+
+        let mut buf = [0; 5];
+        reader.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+    }
+
+    #[tokio::test]
+    async fn zero_length_write() {
+        let (mut writer, mut reader) = pipe_with_capacity(5);
+
+        let write = tokio::spawn(async move {
+            writer.write_all(b"hello").await.unwrap();
+            // Returns right away although the pipe is full.
+            assert_eq!(writer.write(&[]).await.unwrap(), 0);
+            writer.write_all(b" world").await.unwrap();
+        });
+
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"hello world");
+        write.await.unwrap();
+    }
+
+    #[test]
+    fn waker_is_stored_once() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::task::{Context, RawWaker, RawWakerVTable, Waker};
+
+        // A waker counting how often it is cloned, `data` points to the counter.
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+        unsafe fn clone(data: *const ()) -> RawWaker {
+            (*(data as *const AtomicUsize)).fetch_add(1, Ordering::SeqCst);
+            RawWaker::new(data, &VTABLE)
+        }
+        unsafe fn noop(_: *const ()) {}
+
+        let clones = AtomicUsize::new(0);
+        let raw = RawWaker::new(&clones as *const AtomicUsize as *const (), &VTABLE);
+        let waker = unsafe { Waker::from_raw(raw) };
+        let mut cx = Context::from_waker(&waker);
+
+        let (_writer, mut reader) = pipe();
+        let mut buf = [0; 4];
+        for _ in 0..3 {
+            let mut read_buf = ReadBuf::new(&mut buf);
+            let poll = AsyncRead::poll_read(Pin::new(&mut reader), &mut cx, &mut read_buf);
+            assert!(poll.is_pending());
+        }
+        assert_eq!(clones.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn throttle_writer() {
+        let (writer, mut reader) = pipe();
+        let mut writer = writer.throttle(2000);
+
+        let start = std::time::Instant::now();
+        let write = tokio::spawn(async move {
+            writer.write_all(&[1; 200]).await.unwrap();
+        });
+
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).await.unwrap();
+        write.await.unwrap();
+
+        assert_eq!(buf.len(), 200);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn take_leaves_the_rest_buffered() {
+        let (mut writer, reader) = pipe();
+        writer.write_all(&[7; 30]).await.unwrap();
+        drop(writer);
+
+        let mut take = reader.take(10);
+        let mut buf = Vec::new();
+        take.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, [7; 10]);
+        assert_eq!(take.read(&mut [0; 4]).await.unwrap(), 0);
+
+        let mut reader = take.into_inner();
+        assert_eq!(reader.buffered_len().unwrap(), 20);
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, [7; 20]);
+    }
+
+    #[tokio::test]
+    async fn tee_into_second_pipe() {
+        let (mut writer, reader) = pipe_with_capacity(4);
+        let (mirror_writer, mut mirror_reader) = pipe_with_capacity(4);
+
+        let write = tokio::spawn(async move {
+            writer.write_all(b"hello world").await.unwrap();
+        });
+        let mirror = tokio::spawn(async move {
+            let mut buf = Vec::new();
+            mirror_reader.read_to_end(&mut buf).await.unwrap();
+            buf
+        });
+
+        let mut tee = reader.tee(mirror_writer);
+        let mut buf = Vec::new();
+        tee.read_to_end(&mut buf).await.unwrap();
+        drop(tee);
+
+        assert_eq!(buf, b"hello world");
+        assert_eq!(mirror.await.unwrap(), b"hello world");
+        write.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn copy_between_pipes() {
+        let (mut writer_a, mut reader_a) = pipe_with_capacity(7);
+        let (mut writer_b, mut reader_b) = pipe_with_capacity(5);
+        let data: Vec<u8> = (0..=255).cycle().take(1000).collect();
+
+        let expected = data.clone();
+        let write = tokio::spawn(async move { writer_a.write_all(&data).await.unwrap() });
+        let copy = tokio::spawn(async move {
+            let copied = copy(&mut reader_a, &mut writer_b).await.unwrap();
+            (copied, writer_b.bytes_written().unwrap())
+        });
+
+        let mut buf = Vec::new();
+        reader_b.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, expected);
+        assert_eq!(copy.await.unwrap(), (1000, 1000));
+        write.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn copy_into_same_pipe() {
+        let (mut writer, mut reader) = pipe();
+        let io_error = copy(&mut reader, &mut writer).await.unwrap_err();
+        assert_eq!(io_error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[tokio::test]
+    async fn write_after_shutdown_fails() {
+        let (mut writer, _reader) = pipe();
+        writer.shutdown().await.unwrap();
+
+        let io_error = writer.write(b"late").await.unwrap_err();
+        assert_eq!(io_error.kind(), io::ErrorKind::BrokenPipe);
+        let io_error = writer
+            .write_vectored(&[IoSlice::new(b"la"), IoSlice::new(b"te")])
+            .await
+            .unwrap_err();
+        assert_eq!(io_error.kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[tokio::test]
+    async fn shutdown_one_clone_keeps_others_writing() {
+        let (mut writer, mut reader) = pipe();
+        let mut clone = writer.clone();
+        writer.write_all(b"hello ").await.unwrap();
+        let mut buf = [0u8; 6];
+        reader.read_exact(&mut buf).await.unwrap();
+        writer.shutdown().await.unwrap();
+
+        assert!(!reader.is_closed().unwrap());
+        let io_error = writer.write(b"late").await.unwrap_err();
+        assert_eq!(io_error.kind(), io::ErrorKind::BrokenPipe);
+        let write = tokio::spawn(async move {
+            clone.write_all(b"world").await.unwrap();
+            clone.shutdown().await.unwrap();
+        });
+
+        let mut read_buf = Vec::new();
+        reader.read_to_end(&mut read_buf).await.unwrap();
+        assert_eq!(&read_buf, b"world");
+        write.await.unwrap();
+        assert_eq!(reader.eof_reason(), Some(EofReason::WriterFinished));
+
+        let (mut writer, mut reader) = pipe();
+        let clone = writer.clone();
+        writer.shutdown().await.unwrap();
+        drop(clone);
+        assert_eq!(reader.read(&mut buf).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn write_after_shutdown_fails_after_reset() {
+        let (mut writer, reader) = pipe();
+        let mut clone = writer.clone();
+        writer.shutdown().await.unwrap();
+        reader.close().unwrap();
+        writer.reset().unwrap();
+
+        let io_error = writer.write(b"late").await.unwrap_err();
+        assert_eq!(io_error.kind(), io::ErrorKind::BrokenPipe);
+        assert_eq!(writer.available().unwrap(), 0);
+        clone.write_all(b"on").await.unwrap();
+
+        reader.close_with(io::ErrorKind::ConnectionReset).unwrap();
+        let io_error = writer.write(b"late").await.unwrap_err();
+        assert_eq!(io_error.kind(), io::ErrorKind::BrokenPipe);
+        let io_error = clone.write(b"late").await.unwrap_err();
+        assert_eq!(io_error.kind(), io::ErrorKind::ConnectionReset);
+
+        let (mut writer, reader) = pipe();
+        writer.shutdown().await.unwrap();
+        assert!(reader.reset().is_err());
+    }
+
+    #[tokio::test]
+    async fn shutdown_twice() {
+        let (mut writer, mut reader) = pipe();
+        writer.shutdown().await.unwrap();
+        writer.shutdown().await.unwrap();
+        assert_eq!(reader.read(&mut [0; 4]).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn shutdown_flushes_pending_data() {
+        let (mut writer, mut reader) = pipe();
+        writer.write_all(b"hello").await.unwrap();
+
+        let shutdown = tokio::spawn(async move {
+            writer.shutdown().await.unwrap();
+            writer
+        });
+        tokio::task::yield_now().await;
+        assert!(!reader.is_closed().unwrap());
+
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"hello");
+        assert!(shutdown.await.unwrap().is_closed().unwrap());
+    }
+
+    #[tokio::test]
+    async fn read_buffered_bytes_after_writer_drop() {
+        let (mut writer, mut reader) = PipeBuilder::new().flush_on_drop(true).build();
+        writer.write_all(b"hello world").await.unwrap();
+        drop(writer);
+
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"hello world");
+    }
+
+    #[tokio::test]
+    async fn discard_buffered_bytes_on_writer_drop() {
+        let (mut writer, mut reader) = PipeBuilder::new().flush_on_drop(false).build();
+        writer.write_all(b"hello world").await.unwrap();
+        let clone = writer.clone();
+        drop(writer);
+        assert_eq!(reader.buffered_len().unwrap(), 11);
+
+        drop(clone);
+        assert_eq!(reader.read(&mut [0; 4]).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn builder_with_capacity_and_initial_bytes() {
+        let (mut writer, mut reader) = PipeBuilder::new().capacity(4).initial(&b"hi "[..]).build();
+        assert_eq!(writer.try_write(b"there").unwrap(), 1);
+
+        let write = tokio::spawn(async move { writer.write_all(b"here").await.unwrap() });
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"hi there");
+        write.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn builder_with_capacity_without_flush_on_drop() {
+        let (mut writer, mut reader) = PipeBuilder::new().capacity(4).flush_on_drop(false).build();
+        assert_eq!(writer.try_write(b"hello").unwrap(), 4);
+        drop(writer);
+        assert_eq!(reader.read(&mut [0; 4]).await.unwrap(), 0);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tokio::test]
+    async fn trace_read() {
+        use std::fmt::Debug;
+        use std::sync::{Arc, Mutex};
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata, Subscriber};
+
+        // Records the fields of every event as `name=value` pairs.
+        #[derive(Clone, Default)]
+        struct Recorder(Arc<Mutex<Vec<String>>>);
+
+        struct Fields(Vec<String>);
+
+        impl Visit for Fields {
+            fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+                self.0.push(format!("{}={:?}", field.name(), value));
+            }
+        }
+
+        impl Subscriber for Recorder {
+            fn enabled(&self, _: &Metadata) -> bool {
+                true
+            }
+            fn new_span(&self, _: &Attributes) -> Id {
+                Id::from_u64(1)
+            }
+            fn record(&self, _: &Id, _: &Record) {}
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+            fn event(&self, event: &Event) {
+                let mut fields = Fields(Vec::new());
+                event.record(&mut fields);
+                self.0.lock().unwrap().push(fields.0.join(" "));
+            }
+            fn enter(&self, _: &Id) {}
+            fn exit(&self, _: &Id) {}
+        }
+
+        let recorder = Recorder::default();
+        let _guard = tracing::subscriber::set_default(recorder.clone());
+
+        let (mut writer, mut reader) = pipe();
+        writer.write_all(b"hello").await.unwrap();
+        reader.read_exact(&mut [0; 5]).await.unwrap();
+
+        let events = recorder.0.lock().unwrap();
+        assert!(events.contains(&"message=poll_read bytes=5 poll=\"ready\"".to_string()));
+    }
+
+    #[tokio::test]
+    async fn named_pipe_in_diagnostics() {
+        let (mut writer, mut reader) = PipeBuilder::new().capacity(1).name("upload-stream").build();
+
+        let io_error = reader.try_read(&mut [0; 4]).unwrap_err();
+        assert_eq!(
+            io_error.to_string(),
+            "async-pipe: PipeReader(upload-stream): No data is available"
+        );
+        writer.write_all(b"a").await.unwrap();
+        let io_error = writer.try_write(b"b").unwrap_err();
+        assert!(io_error.to_string().contains("PipeWriter(upload-stream)"));
+        assert!(format!("{:?}", reader).starts_with("PipeReader { name: \"upload-stream\", "));
+
+        let (_writer, mut reader) = pipe();
+        let io_error = reader.try_read(&mut [0; 4]).unwrap_err();
+        assert_eq!(
+            io_error.to_string(),
+            "async-pipe: PipeReader: No data is available"
+        );
+    }
+
+    #[tokio::test]
+    async fn named_pipe_closed_write_error() {
+        let (mut writer, reader) = PipeBuilder::new().name("upload-stream").build();
+        drop(reader);
+
+        let io_error = writer.write_all(b"hello").await.unwrap_err();
+        assert_eq!(io_error.kind(), io::ErrorKind::BrokenPipe);
+        assert_eq!(
+            io_error.to_string(),
+            "async-pipe: PipeWriter(upload-stream): The channel is closed"
+        );
+        let closed_error = io_error
+            .get_ref()
+            .and_then(|err| err.downcast_ref::<crate::ClosedError>())
+            .unwrap();
+        assert_eq!(closed_error.name(), Some("upload-stream"));
+        assert_eq!(
+            closed_error.pipe_error(),
+            crate::PipeError::Closed(io::ErrorKind::BrokenPipe)
+        );
+        let source = std::error::Error::source(closed_error).unwrap();
+        assert_eq!(
+            source.downcast_ref::<crate::PipeError>(),
+            Some(&crate::PipeError::Closed(io::ErrorKind::BrokenPipe))
+        );
+    }
+
+    #[tokio::test]
+    async fn read_timeout() {
+        let (mut writer, mut reader) = pipe();
+        let write = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            writer.write_all(b"late").await.unwrap();
+        });
+
+        let mut buf = [0; 4];
+        let io_error = reader
+            .read_timeout(&mut buf, Duration::from_millis(10))
+            .await
+            .unwrap_err();
+        assert_eq!(io_error.kind(), io::ErrorKind::TimedOut);
+        assert!(crate::state::lock(&reader.state)
+            .reader(reader.id)
+            .waker
+            .is_none());
+
+        write.await.unwrap();
+        let n = reader
+            .read_timeout(&mut buf, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(&buf[..n], b"late");
+    }
+
+    #[test]
+    fn coalesce_small_writes() {
+        use std::task::{Context, Waker};
+
+        let wakes = Arc::new(CountWakes::default());
+        let waker = Waker::from(Arc::clone(&wakes));
+        let mut cx = Context::from_waker(&waker);
+
+        let (mut writer, mut reader) = PipeBuilder::new().coalesce(100).build();
+        let mut buf = [0; 1024];
+        let mut read = 0;
+        let mut poll_read = |reader: &mut super::PipeReader| {
+            let mut read_buf = ReadBuf::new(&mut buf);
+            let poll = AsyncRead::poll_read(Pin::new(reader), &mut cx, &mut read_buf);
+            (poll.is_pending(), read_buf.filled().len())
+        };
+
+        let mut woken = 0;
+        assert!(poll_read(&mut reader).0);
+        for _ in 0..1000 {
+            assert_eq!(writer.try_write(&[1]).unwrap(), 1);
+            if wakes.0.load(Ordering::SeqCst) > woken {
+                woken += 1;
+                let (_, n) = poll_read(&mut reader);
+                read += n;
+                assert!(poll_read(&mut reader).0);
+            }
+        }
+
+        assert_eq!(read, 1000);
+        assert_eq!(woken, 10);
+    }
+
+    #[test]
+    fn handle_counts() {
+        let (writer, reader) = pipe();
+        assert_eq!(writer.writer_count().unwrap(), 1);
+        assert_eq!(reader.reader_count().unwrap(), 1);
+
+        let clone = writer.clone();
+        assert_eq!(writer.writer_count().unwrap(), 2);
+        assert_eq!(reader.writer_count().unwrap(), 2);
+
+        drop(clone);
+        assert_eq!(writer.writer_count().unwrap(), 1);
+        assert_eq!(writer.reader_count().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn weak_writer() {
+        let (writer, mut reader) = pipe();
+        let weak = writer.downgrade();
+        assert_eq!(writer.writer_count().unwrap(), 1);
+
+        let mut upgraded = weak.upgrade().unwrap();
+        upgraded.write_all(b"hello").await.unwrap();
+        drop(upgraded);
+        drop(writer);
+
+        assert!(weak.upgrade().is_none());
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"hello");
+    }
+
+    #[tokio::test]
+    async fn write_from_dropped_buffer() {
+        let (mut writer, mut reader) = pipe();
+
+        let data = vec![7u8; 100];
+        assert_eq!(writer.write(&data).await.unwrap(), 100);
+        drop(data);
+        drop(writer);
+
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, [7; 100]);
+    }
+
+    #[tokio::test]
+    async fn cancelled_read() {
+        let (mut writer, mut reader) = pipe();
+        let mut buf = [0; 16];
+
+        tokio::select! {
+            _ = reader.read(&mut buf) => panic!("nothing was written"),
+            _ = tokio::time::sleep(Duration::from_millis(10)) => {}
+        }
+
+        writer.write_all(b"hello").await.unwrap();
+        assert_eq!(reader.read(&mut buf).await.unwrap(), 5);
+        assert_eq!(&buf[..5], b"hello");
+    }
+
+    #[tokio::test]
+    async fn cancelled_write() {
+        let (mut writer, mut reader) = pipe_with_capacity(4);
+        writer.write_all(b"full").await.unwrap();
+
+        tokio::select! {
+            _ = writer.write(b"more") => panic!("the pipe is full"),
+            _ = tokio::time::sleep(Duration::from_millis(10)) => {}
+        }
+
+        let mut buf = [0; 4];
+        reader.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"full");
+        writer.write_all(b"next").await.unwrap();
+        reader.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"next");
+        assert_eq!(reader.buffered_len().unwrap(), 0);
+    }
+
+    #[cfg(feature = "bytes")]
+    #[tokio::test]
+    async fn write_all_buf() {
+        use bytes::{Buf, Bytes};
+
+        let (mut writer, mut reader) = pipe_with_capacity(4);
+        let mut buf = Bytes::from_static(b"hello ")
+            .chain(Bytes::from_static(b"chained "))
+            .chain(Bytes::from_static(b"world"));
+
+        let write = tokio::spawn(async move {
+            writer.write_all_buf(&mut buf).await.unwrap();
+            assert!(!buf.has_remaining());
+        });
+
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).await.unwrap();
+        assert_eq!(out, b"hello chained world");
+        write.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn size_hint() {
+        let (mut writer, reader) = pipe();
+        assert_eq!(reader.size_hint(), (0, None));
+        writer.write_all(b"hello").await.unwrap();
+        assert_eq!(reader.size_hint(), (5, None));
+        drop(writer);
+        assert_eq!(reader.size_hint(), (5, Some(5)));
+
+        let (mut writer, mut reader) = PipeBuilder::new().content_length(11).build();
+        writer.write_all(b"hello").await.unwrap();
+        assert_eq!(reader.size_hint(), (5, Some(11)));
+        let mut buf = [0; 3];
+        reader.read_exact(&mut buf).await.unwrap();
+        assert_eq!(reader.size_hint(), (2, Some(8)));
+    }
+
+    #[test]
+    fn parked_writer_fails_when_reader_is_dropped() {
+        use std::task::{Context, Poll, Waker};
+
+        let wakes = Arc::new(CountWakes::default());
+        let waker = Waker::from(Arc::clone(&wakes));
+        let mut cx = Context::from_waker(&waker);
+
+        let (mut writer, reader) = pipe_with_capacity(4);
+        assert_eq!(writer.try_write(b"full").unwrap(), 4);
+        assert!(Pin::new(&mut writer)
+            .poll_write(&mut cx, b"more")
+            .is_pending());
+
+        drop(reader);
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 1);
+        match Pin::new(&mut writer).poll_write(&mut cx, b"more") {
+            Poll::Ready(Err(err)) => assert_eq!(err.kind(), io::ErrorKind::BrokenPipe),
+            poll => panic!("expected BrokenPipe, got {:?}", poll),
+        }
+    }
+
+    #[test]
+    fn watermarks() {
+        use std::task::{Context, Waker};
+
+        let wakes = Arc::new(CountWakes::default());
+        let waker = Waker::from(Arc::clone(&wakes));
+        let mut cx = Context::from_waker(&waker);
+
+        let (mut writer, mut reader) = PipeBuilder::new().capacity(16).watermarks(4, 8).build();
+        assert_eq!(writer.try_write(&[1; 16]).unwrap(), 8);
+        assert!(Pin::new(&mut writer)
+            .poll_write(&mut cx, &[1; 16])
+            .is_pending());
+
+        let mut buf = [0; 2];
+        for _ in 0..2 {
+            assert_eq!(reader.try_read(&mut buf).unwrap(), 2);
+            assert_eq!(wakes.0.load(Ordering::SeqCst), 0);
+        }
+        assert_eq!(reader.try_read(&mut buf).unwrap(), 2);
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 1);
+
+        match Pin::new(&mut writer).poll_write(&mut cx, &[1; 16]) {
+            std::task::Poll::Ready(Ok(n)) => assert_eq!(n, 6),
+            poll => panic!("expected a write, got {:?}", poll),
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn low_watermark_above_high_panics() {
+        PipeBuilder::new().watermarks(8, 4);
+    }
+
+    #[tokio::test]
+    async fn inspect() {
+        let (mut writer, reader) = pipe_with_capacity(4);
+        let data: Vec<u8> = (0..=255).collect();
+        let expected: u64 = data.iter().map(|&b| u64::from(b)).sum();
+
+        let write = tokio::spawn(async move { writer.write_all(&data).await.unwrap() });
+
+        let mut sum = 0;
+        let mut reader =
+            reader.inspect(|bytes| sum += bytes.iter().map(|&b| u64::from(b)).sum::<u64>());
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).await.unwrap();
+        drop(reader);
+
+        assert_eq!(buf.len(), 256);
+        assert_eq!(sum, expected);
+        write.await.unwrap();
+    }
+
+    #[cfg(feature = "crc32fast")]
+    #[tokio::test]
+    async fn checksum() {
+        let (mut writer, reader) = pipe_with_capacity(4);
+        let data = b"The quick brown fox jumps over the lazy dog";
+
+        let write = tokio::spawn(async move { writer.write_all(data).await.unwrap() });
+
+        let mut reader = reader.with_checksum();
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, data);
+        assert_eq!(reader.len(), data.len() as u64);
+        assert_eq!(reader.finalize(), crc32fast::hash(data));
+        assert_eq!(reader.finalize(), 0x414f_a339);
+        write.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn chain() {
+        let (mut writer, reader) = pipe_from(&b"header\n"[..]);
+        writer.write_all(b"live").await.unwrap();
+        drop(writer);
+
+        let mut reader = reader.chain(io::Cursor::new(b" trailer".to_vec()));
+        let mut buf = [0; 64];
+        assert_eq!(reader.read(&mut buf).await.unwrap(), 11);
+        assert_eq!(&buf[..11], b"header\nlive");
+
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).await.unwrap();
+        assert_eq!(rest, b" trailer");
+    }
+
+    #[tokio::test]
+    async fn flushed() {
+        let (mut writer, mut reader) = pipe();
+        writer.flushed().await;
+        writer.write_all(b"hello").await.unwrap();
+
+        let read = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            let mut buf = [0; 5];
+            reader.read_exact(&mut buf).await.unwrap();
+            reader
+        });
+
+        writer.flushed().await;
+        assert!(writer.is_flushed().unwrap());
+        let reader = read.await.unwrap();
+        assert_eq!(reader.bytes_read().unwrap(), 5);
+    }
+
+    #[test]
+    fn spurious_polls() {
+        use std::task::{Context, Poll, Waker};
+
+        let (mut writer, mut reader) = pipe();
+        let mut buf = [0; 16];
+        let mut noop = Context::from_waker(Waker::noop());
+        for _ in 0..3 {
+            let mut read_buf = ReadBuf::new(&mut buf);
+            let poll = AsyncRead::poll_read(Pin::new(&mut reader), &mut noop, &mut read_buf);
+            assert!(poll.is_pending());
+        }
+
+        // The task polling last is the one woken.
+        let wakes = Arc::new(CountWakes::default());
+        let waker = Waker::from(Arc::clone(&wakes));
+        let mut cx = Context::from_waker(&waker);
+        let mut read_buf = ReadBuf::new(&mut buf);
+        assert!(AsyncRead::poll_read(Pin::new(&mut reader), &mut cx, &mut read_buf).is_pending());
+
+        assert_eq!(writer.try_write(b"hello").unwrap(), 5);
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 1);
+        let mut read_buf = ReadBuf::new(&mut buf);
+        match AsyncRead::poll_read(Pin::new(&mut reader), &mut cx, &mut read_buf) {
+            Poll::Ready(Ok(())) => assert_eq!(read_buf.filled(), b"hello"),
+            poll => panic!("expected data, got {:?}", poll),
+        }
+    }
+
+    #[test]
+    fn poll_impl() {
+        use std::task::{Context, Poll, Waker};
+
+        let mut cx = Context::from_waker(Waker::noop());
+        let (mut writer, mut reader) = pipe();
+        let mut buf = [0; 16];
+
+        assert!(Pin::new(&mut reader)
+            .poll_read_impl(&mut cx, &mut buf)
+            .is_pending());
+        match Pin::new(&mut writer).poll_write_impl(&mut cx, b"hello") {
+            Poll::Ready(Ok(n)) => assert_eq!(n, 5),
+            poll => panic!("expected a write, got {:?}", poll),
+        }
+        match Pin::new(&mut reader).poll_read_impl(&mut cx, &mut buf) {
+            Poll::Ready(Ok(n)) => assert_eq!(&buf[..n], b"hello"),
+            poll => panic!("expected data, got {:?}", poll),
+        }
+    }
+
+    #[tokio::test]
+    async fn duplex_split_unsplit() {
+        use super::{DuplexPipe, UnsplitError};
+
+        let (a, b) = duplex();
+        let (a_writer, a_reader) = a.split();
+        let (b_writer, b_reader) = b.split();
+
+        // The writer of one end and the reader of the other are two halves of the same pipe.
+        let UnsplitError(a_writer, b_reader) = DuplexPipe::unsplit(a_writer, b_reader).unwrap_err();
+        let (c, _d) = duplex();
+        let (c_writer, c_reader) = c.split();
+        let UnsplitError(a_writer, c_reader) = DuplexPipe::unsplit(a_writer, c_reader).unwrap_err();
+        assert!(DuplexPipe::unsplit(c_writer, c_reader).is_ok());
+
+        let mut a = DuplexPipe::unsplit(a_writer, a_reader).unwrap();
+        let mut b = DuplexPipe::unsplit(b_writer, b_reader).unwrap();
+        a.write_all(b"ping").await.unwrap();
+        let mut buf = [0; 4];
+        b.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rate_tracking() {
+        let (mut writer, mut reader) = PipeBuilder::new().with_rate_tracking().build();
+        assert_eq!(pipe().0.bytes_per_sec(), None);
+
+        let read = tokio::spawn(async move {
+            let mut buf = Vec::new();
+            reader.read_to_end(&mut buf).await.unwrap();
+            reader
+        });
+
+        // 100 bytes every 10 milliseconds for half a second, 10000 bytes per second.
+        for _ in 0..50 {
+            writer.write_all(&[0; 100]).await.unwrap();
+            tokio::time::advance(Duration::from_millis(10)).await;
+        }
+        assert_eq!(writer.bytes_per_sec().unwrap(), 10000.0);
+
+        drop(writer);
+        let reader = read.await.unwrap();
+        assert_eq!(reader.bytes_per_sec().unwrap(), 10000.0);
+    }
+
+    #[tokio::test]
+    async fn flush_keeps_pipe_open() {
+        let (mut writer, mut reader) = pipe();
+
+        let read = tokio::spawn(async move {
+            let mut buf = Vec::new();
+            reader.read_to_end(&mut buf).await.unwrap();
+            buf
+        });
+
+        writer.write_all(b"hello ").await.unwrap();
+        writer.flush().await.unwrap();
+        assert!(!writer.is_closed().unwrap());
+        writer.write_all(b"world").await.unwrap();
+        writer.shutdown().await.unwrap();
+        assert!(writer.is_closed().unwrap());
+
+        assert_eq!(read.await.unwrap(), b"hello world");
+    }
+
+    #[tokio::test]
+    async fn available() {
+        let (mut writer, mut reader) = pipe_with_capacity(8);
+        assert_eq!(writer.capacity(), 8);
+        assert_eq!(writer.available().unwrap(), 8);
+
+        writer.write_all(b"hello").await.unwrap();
+        assert_eq!(writer.available().unwrap(), 3);
+        let mut buf = [0; 2];
+        reader.read_exact(&mut buf).await.unwrap();
+        assert_eq!(
+            writer.available().unwrap(),
+            8 - reader.buffered_len().unwrap()
+        );
+
+        reader.close().unwrap();
+        assert_eq!(writer.available().unwrap(), 0);
+        assert_eq!(writer.capacity(), 8);
+    }
+
+    #[cfg(feature = "framed")]
+    #[tokio::test]
+    async fn framed() {
+        let (writer, reader) = pipe_with_capacity(3);
+        let frames: Vec<Vec<u8>> =
+            vec![b"hello".to_vec(), Vec::new(), vec![7; 2000], b"!".to_vec()];
+
+        let sent = frames.clone();
+        let write = tokio::spawn(async move {
+            let mut writer = writer.framed();
+            for frame in &sent {
+                writer.send(frame).await.unwrap();
+            }
+        });
+
+        let mut reader = reader.framed();
+        let mut received = Vec::new();
+        while let Some(frame) = reader.next_frame().await.unwrap() {
+            received.push(frame);
+        }
+        assert_eq!(received, frames);
+        write.await.unwrap();
+    }
+
+    #[cfg(feature = "framed")]
+    #[tokio::test]
+    async fn framed_eof_in_frame() {
+        let (mut writer, reader) = pipe();
+        writer.write_all(&[0, 0, 0, 5, b'h', b'i']).await.unwrap();
+        drop(writer);
+
+        let err = reader.framed().next_frame().await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn close_races_parked_writer() {
+        for _ in 0..200 {
+            let (mut writer, reader) = pipe_with_capacity(1);
+            let write = tokio::spawn(async move { writer.write_all(b"ab").await });
+            let close = tokio::spawn(async move { reader.close().unwrap() });
+
+            let result = tokio::time::timeout(Duration::from_secs(5), write)
+                .await
+                .expect("the writer was not woken")
+                .unwrap();
+            assert_eq!(result.unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+            close.await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn read_exact_or_eof() {
+        let (mut writer, mut reader) = pipe();
+        writer.write_all(b"hello").await.unwrap();
+        drop(writer);
+
+        let mut buf = [0; 10];
+        assert_eq!(reader.read_exact_or_eof(&mut buf).await.unwrap(), 5);
+        assert_eq!(&buf[..5], b"hello");
+        assert_eq!(reader.read_exact_or_eof(&mut buf).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn boxed_trait_objects() {
+        fn assert_send_sync_unpin<T: Send + Sync + Unpin>() {}
+        assert_send_sync_unpin::<super::PipeReader>();
+        assert_send_sync_unpin::<super::PipeWriter>();
+
+        let (writer, reader) = pipe();
+        let (other_writer, other_reader) = pipe();
+        let mut writers: Vec<Box<dyn AsyncWrite + Unpin + Send>> =
+            vec![writer.into_dyn(), other_writer.into()];
+        let mut readers: Vec<Box<dyn AsyncRead + Unpin + Send>> =
+            vec![reader.into_dyn(), other_reader.into()];
+
+        for writer in &mut writers {
+            writer.write_all(b"hi").await.unwrap();
+        }
+        drop(writers);
+        for reader in &mut readers {
+            let mut buf = Vec::new();
+            reader.read_to_end(&mut buf).await.unwrap();
+            assert_eq!(buf, b"hi");
+        }
+    }
+
+    #[tokio::test]
+    async fn close_discarding() {
+        let (mut writer, mut reader) = pipe();
+        writer.write_all(b"hello world").await.unwrap();
+
+        assert_eq!(writer.close_discarding().unwrap(), 11);
+        assert_eq!(reader.buffered_len().unwrap(), 0);
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).await.unwrap();
+        assert!(buf.is_empty());
+        assert_eq!(writer.close_discarding().unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn seek() {
+        use tokio::io::AsyncSeekExt;
+
+        let (mut writer, mut reader) = pipe();
+        writer.write_all(b"hello").await.unwrap();
+        let mut buf = [0; 3];
+        reader.read_exact(&mut buf).await.unwrap();
+
+        assert_eq!(reader.stream_position().await.unwrap(), 3);
+        let err = reader.seek(io::SeekFrom::Start(0)).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+        let err = reader.seek(io::SeekFrom::Current(1)).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+        assert_eq!(reader.seek(io::SeekFrom::Current(0)).await.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn write_all_counted() {
+        let (mut writer, mut reader) = pipe_with_capacity(4);
+        assert_eq!(writer.write_all_counted(b"ab").await.unwrap(), 2);
+        let mut buf = [0; 2];
+        reader.read_exact(&mut buf).await.unwrap();
+
+        let write = tokio::spawn(async move { writer.write_all_counted(&[1; 12]).await });
+
+        let mut buf = [0; 4];
+        reader.read_exact(&mut buf).await.unwrap();
+        // Wait for the writer to fill the pipe again before closing it.
+        while reader.buffered_len().unwrap() < 4 {
+            tokio::task::yield_now().await;
+        }
+        reader.close().unwrap();
+
+        let (err, written) = write.await.unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        assert_eq!(written, 8);
+    }
+
+    #[tokio::test]
+    async fn read_into_uninit() {
+        use std::mem::MaybeUninit;
+
+        let (mut writer, mut reader) = pipe();
+        writer.write_all(b"hello").await.unwrap();
+
+        let mut storage = [MaybeUninit::<u8>::uninit(); 16];
+        let mut buf = ReadBuf::uninit(&mut storage);
+        std::future::poll_fn(|cx| Pin::new(&mut reader).poll_read(cx, &mut buf))
+            .await
+            .unwrap();
+        assert_eq!(buf.filled(), b"hello");
+        assert_eq!(buf.initialized().len(), 5);
+    }
+
+    #[tokio::test]
+    async fn adapters_read_into_uninit() {
+        use std::mem::MaybeUninit;
+
+        async fn read_uninit<R: AsyncRead + Unpin>(reader: &mut R) -> (Vec<u8>, usize) {
+            let mut storage = [MaybeUninit::<u8>::uninit(); 16];
+            let mut buf = ReadBuf::uninit(&mut storage);
+            std::future::poll_fn(|cx| Pin::new(&mut *reader).poll_read(cx, &mut buf))
+                .await
+                .unwrap();
+            (buf.filled().to_vec(), buf.initialized().len())
+        }
+
+        let (mut writer, reader) = pipe();
+        writer.write_all(b"hello world").await.unwrap();
+        let mut take = reader.take(5);
+        assert_eq!(read_uninit(&mut take).await, (b"hello".to_vec(), 5));
+
+        let mut seen = Vec::new();
+        let mut inspect = take
+            .into_inner()
+            .inspect(|bytes| seen.extend_from_slice(bytes));
+        assert_eq!(read_uninit(&mut inspect).await, (b" world".to_vec(), 6));
+        let reader = inspect.into_inner();
+        assert_eq!(seen, b" world");
+
+        writer.write_all(b"mirror").await.unwrap();
+        let mut tee = reader.tee(Vec::new());
+        assert_eq!(read_uninit(&mut tee).await, (b"mirror".to_vec(), 6));
+        let (reader, sink) = tee.into_inner();
+        assert_eq!(sink, b"mirror");
+
+        writer.write_all(b"first").await.unwrap();
+        drop(writer);
+        let mut chain = reader.chain(&b"second"[..]);
+        assert_eq!(read_uninit(&mut chain).await, (b"first".to_vec(), 5));
+        assert_eq!(read_uninit(&mut chain).await, (b"second".to_vec(), 6));
+    }
+
+    #[cfg(feature = "crc32fast")]
+    #[tokio::test]
+    async fn checksum_read_into_uninit() {
+        use std::mem::MaybeUninit;
+
+        let (mut writer, reader) = pipe();
+        writer.write_all(b"hello").await.unwrap();
+        let mut reader = reader.with_checksum();
+
+        let mut storage = [MaybeUninit::<u8>::uninit(); 16];
+        let mut buf = ReadBuf::uninit(&mut storage);
+        std::future::poll_fn(|cx| Pin::new(&mut reader).poll_read(cx, &mut buf))
+            .await
+            .unwrap();
+        assert_eq!(buf.filled(), b"hello");
+        assert_eq!(buf.initialized().len(), 5);
+        assert_eq!(reader.len(), 5);
+        assert_eq!(reader.finalize(), crc32fast::hash(b"hello"));
+    }
+
+    #[tokio::test]
+    async fn drain_to_vec() {
+        let (mut writer, mut reader) = pipe_with_capacity(16);
+        let chunks: Vec<Vec<u8>> = (0..50u8).map(|i| vec![i; usize::from(i % 7) + 1]).collect();
+
+        let sent = chunks.clone();
+        let write = tokio::spawn(async move {
+            for chunk in &sent {
+                writer.write_all(chunk).await.unwrap();
+            }
+        });
+
+        assert_eq!(reader.drain_to_vec().await.unwrap(), chunks.concat());
+        write.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn poll_budget_lets_a_hot_reader_and_writer_take_turns() {
+        let (mut writer, mut reader) = PipeBuilder::new().capacity(1 << 20).poll_budget(8).build();
+        let written = Arc::new(AtomicUsize::new(0));
+        let read = Arc::new(AtomicUsize::new(0));
+        let max_lag = Arc::new(AtomicUsize::new(0));
+
+        let writer_task = {
+            let (written, read, max_lag) = (written.clone(), read.clone(), max_lag.clone());
+            tokio::spawn(async move {
+                for _ in 0..10_000 {
+                    writer.write_all(b"x").await.unwrap();
+                    let written = written.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_lag.fetch_max(written - read.load(Ordering::SeqCst), Ordering::SeqCst);
+                }
+            })
+        };
+        let reader_task = {
+            let read = read.clone();
+            tokio::spawn(async move {
+                let mut buf = [0; 1];
+                while reader.read(&mut buf).await.unwrap() > 0 {
+                    read.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+        };
+
+        writer_task.await.unwrap();
+        reader_task.await.unwrap();
+
+        assert_eq!(written.load(Ordering::SeqCst), 10_000);
+        assert_eq!(read.load(Ordering::SeqCst), 10_000);
+        assert!(max_lag.load(Ordering::SeqCst) <= 20);
+    }
+
+    #[test]
+    #[should_panic(expected = "poll budget must be non-zero")]
+    fn zero_poll_budget_panics() {
+        PipeBuilder::new().poll_budget(0);
+    }
+
+    #[cfg(feature = "tokio-util")]
+    #[tokio::test]
+    async fn cancelling_the_token_closes_the_pipe() {
+        let token = tokio_util::sync::CancellationToken::new();
+        let (mut writer, mut reader) = PipeBuilder::new().with_cancellation(token.clone()).build();
+
+        let read = tokio::spawn(async move {
+            let mut buf = [0; 4];
+            reader.read(&mut buf).await.unwrap()
+        });
+        tokio::task::yield_now().await;
+        token.cancel();
+
+        assert_eq!(read.await.unwrap(), 0);
+        writer.closed().await;
+        let err = writer.write_all(b"late").await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[cfg(feature = "tokio-util")]
+    #[tokio::test]
+    async fn closing_the_pipe_cancels_the_token() {
+        let token = tokio_util::sync::CancellationToken::new();
+        let (writer, reader) = PipeBuilder::new().with_cancellation(token.clone()).build();
+
+        assert!(!token.is_cancelled());
+        writer.close().unwrap();
+        assert!(token.is_cancelled());
+        assert!(reader.reset().is_err());
+    }
+
+    #[test]
+    fn poll_read_contract() {
+        use std::task::{Context, Poll, Waker};
+
+        let mut cx = Context::from_waker(Waker::noop());
+        let (writer, mut reader) = PipeBuilder::new().initial(*b"ab").build();
+
+        // An empty buffer reads nothing, and it doesn't mean EOF.
+        let poll = Pin::new(&mut reader).poll_read_impl(&mut cx, &mut []);
+        assert!(matches!(poll, Poll::Ready(Ok(0))));
+        assert_eq!(reader.bytes_read().unwrap(), 0);
+
+        let mut buf = [0; 4];
+        let poll = Pin::new(&mut reader).poll_read_impl(&mut cx, &mut buf);
+        assert!(matches!(poll, Poll::Ready(Ok(2))));
+        assert_eq!(&buf[..2], b"ab");
+
+        let poll = Pin::new(&mut reader).poll_read_impl(&mut cx, &mut buf);
+        assert!(poll.is_pending());
+
+        drop(writer);
+        let poll = Pin::new(&mut reader).poll_read_impl(&mut cx, &mut buf);
+        assert!(matches!(poll, Poll::Ready(Ok(0))));
+    }
+
+    #[test]
+    fn empty_read_on_an_empty_open_pipe_is_not_eof() {
+        use std::task::{Context, Poll, Waker};
+
+        let mut cx = Context::from_waker(Waker::noop());
+        let (mut writer, mut reader) = pipe();
+
+        let poll = Pin::new(&mut reader).poll_read_impl(&mut cx, &mut []);
+        assert!(matches!(poll, Poll::Ready(Ok(0))));
+
+        assert!(matches!(
+            Pin::new(&mut writer).poll_write_impl(&mut cx, b"x"),
+            Poll::Ready(Ok(1))
+        ));
+        let mut buf = [0; 1];
+        let poll = Pin::new(&mut reader).poll_read_impl(&mut cx, &mut buf);
+        assert!(matches!(poll, Poll::Ready(Ok(1))));
+    }
+
+    #[tokio::test]
+    async fn by_ref_take_leaves_the_reader_usable() {
+        let (mut writer, mut reader) = pipe();
+        writer.write_all(b"headerbody").await.unwrap();
+        drop(writer);
+
+        let mut header = Vec::new();
+        reader
+            .by_ref()
+            .take(6)
+            .read_to_end(&mut header)
+            .await
+            .unwrap();
+        assert_eq!(header, b"header");
+
+        let mut body = Vec::new();
+        reader.read_to_end(&mut body).await.unwrap();
+        assert_eq!(body, b"body");
+    }
+
+    #[tokio::test]
+    async fn eof_reason_after_shutdown_or_drop() {
+        let (mut writer, mut reader) = pipe();
+        let write = tokio::spawn(async move {
+            writer.write_all(b"done").await.unwrap();
+            // Shutting down waits for the reader to drain the pipe.
+            writer.shutdown().await.unwrap();
+            writer
+        });
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(reader.eof_reason(), Some(EofReason::WriterFinished));
+        drop(write.await.unwrap());
+
+        let (mut writer, mut reader) = pipe();
+        writer.write_all(b"done").await.unwrap();
+        drop(writer);
+        assert_eq!(reader.eof_reason(), None);
+        reader.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(reader.eof_reason(), Some(EofReason::WriterFinished));
+    }
+
+    #[tokio::test]
+    async fn eof_reason_after_close() {
+        let (mut writer, mut reader) = pipe();
+        assert_eq!(reader.eof_reason(), None);
+        writer.write_all(b"trunc").await.unwrap();
+        writer.close().unwrap();
+        // Dropping the writer afterwards doesn't change the reason.
+        drop(writer);
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(reader.eof_reason(), Some(EofReason::Aborted));
+
+        let (_writer, reader) = pipe();
+        reader.close().unwrap();
+        assert_eq!(reader.eof_reason(), Some(EofReason::Aborted));
+    }
+
+    #[test]
+    fn large_write_all_fills_the_buffer_per_round() {
+        use std::future::Future;
+        use std::task::{Context, Poll, Waker};
+
+        let data = vec![7; 64 * 1024];
+        let (mut writer, mut reader) = pipe_with_capacity(1024);
+        let reader_wakes = Arc::new(CountWakes(AtomicUsize::new(0)));
+        let reader_waker = Waker::from(Arc::clone(&reader_wakes));
+        let mut reader_cx = Context::from_waker(&reader_waker);
+        let mut writer_cx = Context::from_waker(Waker::noop());
+
+        let mut write = Box::pin(writer.write_all(&data));
+        let mut buf = vec![0; 4096];
+        let mut received = 0;
+        let mut rounds = 0;
+        loop {
+            rounds += 1;
+            let done = write.as_mut().poll(&mut writer_cx).is_ready();
+            while let Poll::Ready(n) =
+                Pin::new(&mut reader).poll_read_impl(&mut reader_cx, &mut buf)
+            {
+                received += n.unwrap();
+            }
+            if done {
+                break;
+            }
+        }
+
+        assert_eq!(received, data.len());
+        // Every round hands over a full buffer, and wakes the reader once.
+        assert_eq!(rounds, data.len() / 1024);
+        assert!(reader_wakes.0.load(Ordering::SeqCst) <= rounds);
+    }
+
+    #[test]
+    fn pipe_id() {
+        let (writer, reader) = pipe();
+        let (other_writer, other_reader) = pipe();
+
+        assert_eq!(writer.pipe_id(), reader.pipe_id());
+        assert_eq!(writer.clone().pipe_id(), writer.pipe_id());
+        assert_ne!(writer.pipe_id(), other_writer.pipe_id());
+        assert_ne!(reader.pipe_id(), other_reader.pipe_id());
+
+        assert_eq!(writer, writer.clone());
+        assert_ne!(writer, other_writer);
+        assert_ne!(reader, other_reader);
+    }
+
+    #[tokio::test]
+    async fn write_larger_than_capacity() {
+        let (mut writer, mut reader) = pipe_with_capacity(64);
+        let message: Vec<u8> = (0..10 * 1024).map(|i| i as u8).collect();
+
+        let read = tokio::spawn(async move {
+            let mut read = Vec::new();
+            reader.read_to_end(&mut read).await.unwrap();
+            read
+        });
+        writer.write_all(&message).await.unwrap();
+        drop(writer);
+
+        assert_eq!(read.await.unwrap(), message);
+    }
+
+    #[test]
+    fn write_takes_any_free_byte() {
+        use std::task::{Context, Poll, Waker};
+
+        let mut cx = Context::from_waker(Waker::noop());
+        let (mut writer, mut reader) = pipe_with_capacity(64);
+        let message = [1; 100];
+
+        let poll = Pin::new(&mut writer).poll_write_impl(&mut cx, &message);
+        assert!(matches!(poll, Poll::Ready(Ok(64))));
+        let poll = Pin::new(&mut reader).poll_read_impl(&mut cx, &mut [0; 1]);
+        assert!(matches!(poll, Poll::Ready(Ok(1))));
+        let poll = Pin::new(&mut writer).poll_write_impl(&mut cx, &message[64..]);
+        assert!(matches!(poll, Poll::Ready(Ok(1))));
+    }
+
+    #[tokio::test]
+    async fn poll_peek() {
+        use std::task::{Context, Poll, Waker};
+
+        let (mut writer, mut reader) = pipe();
+        let wakes = Arc::new(CountWakes(AtomicUsize::new(0)));
+        let waker = Waker::from(Arc::clone(&wakes));
+        let mut cx = Context::from_waker(&waker);
+        let mut buf = [0; 4];
+
+        assert!(reader.poll_peek(&mut cx, &mut buf).is_pending());
+        writer.write_all(b"peek").await.unwrap();
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 1);
+
+        let poll = reader.poll_peek(&mut cx, &mut buf[..2]);
+        assert!(matches!(poll, Poll::Ready(Ok(2))));
+        assert_eq!(&buf[..2], b"pe");
+        let poll = reader.poll_peek(&mut cx, &mut buf);
+        assert!(matches!(poll, Poll::Ready(Ok(4))));
+
+        reader.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"peek");
+    }
+
+    #[tokio::test]
+    async fn on_close_runs_once_with_the_totals() {
+        let calls = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let (mut writer, mut reader) = {
+            let calls = Arc::clone(&calls);
+            PipeBuilder::new()
+                .on_close(move |stats| calls.lock().unwrap().push(stats))
+                .build()
+        };
+        let clone = writer.clone();
+
+        writer.write_all(b"0123456789").await.unwrap();
+        reader.read_exact(&mut [0; 4]).await.unwrap();
+        drop(writer);
+        assert!(calls.lock().unwrap().is_empty());
+        drop(clone);
+        drop(reader);
+
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [CloseStats {
+                bytes_written: 10,
+                bytes_read: 4,
+                reason: EofReason::WriterFinished,
+            }]
+        );
+    }
+
+    #[test]
+    fn on_close_runs_once_under_concurrent_drops() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let (writer, reader) = {
+            let calls = Arc::clone(&calls);
+            PipeBuilder::new()
+                .on_close(move |_| {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                })
+                .build()
+        };
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let writer = writer.clone();
+                std::thread::spawn(move || writer.close().unwrap())
+            })
+            .chain(std::iter::once(std::thread::spawn(move || drop(reader))))
+            .collect();
+        drop(writer);
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[cfg(feature = "bytes")]
+    #[tokio::test]
+    async fn read_buf_into_bytes_mut() {
+        use bytes::{Bytes, BytesMut};
+
+        let (mut writer, mut reader) = pipe();
+        writer
+            .write_bytes(Bytes::from_static(b"chunk "))
+            .await
+            .unwrap();
+        writer.write_all(b"copied").await.unwrap();
+
+        let mut buf = BytesMut::new();
+        assert_eq!(reader.read_buf(&mut buf).await.unwrap(), 12);
+        assert_eq!(&buf[..], b"chunk copied");
+
+        let mut limited = [0; 2];
+        let mut limited = &mut limited[..];
+        writer.write_all(b"abc").await.unwrap();
+        assert_eq!(reader.read_buf(&mut limited).await.unwrap(), 2);
+        drop(writer);
+        buf.clear();
+        assert_eq!(reader.read_buf(&mut buf).await.unwrap(), 1);
+        assert_eq!(&buf[..], b"c");
+        assert_eq!(reader.read_buf(&mut buf).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn large_capacity_is_allocated_lazily() {
+        let (mut writer, _reader) = pipe_with_capacity(usize::MAX);
+        assert!(crate::state::lock(&writer.state).buffer.allocated() <= 1024);
+        writer.write_all(&[0; 4096]).await.unwrap();
+
+        let (mut writer, _reader) = pipe_with_capacity(3000);
+        writer.write_all(&[0; 3000]).await.unwrap();
+        let allocated = crate::state::lock(&writer.state).buffer.allocated();
+        assert!((3000..4096).contains(&allocated));
+    }
+
+    #[tokio::test]
+    async fn buffer_capacity_is_allocated_up_front() {
+        let (mut writer, _reader) = PipeBuilder::new()
+            .capacity(1 << 20)
+            .buffer_capacity(4096)
+            .build();
+        let allocated = crate::state::lock(&writer.state).buffer.allocated();
+        assert!(allocated >= 4096);
+        assert!(allocated < 1 << 20);
+
+        let (unsized_writer, _reader) = PipeBuilder::new().capacity(1 << 20).build();
+        assert!(crate::state::lock(&unsized_writer.state).buffer.allocated() < 4096);
+
+        for _ in 0..16 {
+            writer.write_all(&[0; 256]).await.unwrap();
+        }
+        assert_eq!(
+            crate::state::lock(&writer.state).buffer.allocated(),
+            allocated
+        );
+    }
+
+    #[tokio::test]
+    async fn flush_fails_once_the_reader_is_dropped() {
+        let (mut writer, reader) = pipe();
+        writer.write_all(b"undelivered").await.unwrap();
+
+        let flush = tokio::spawn(async move { writer.flush().await });
+        tokio::task::yield_now().await;
+        assert!(!flush.is_finished());
+        drop(reader);
+
+        let io_error = flush.await.unwrap().unwrap_err();
+        assert_eq!(io_error.kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[tokio::test]
+    async fn unread() {
+        let (mut writer, mut reader) = pipe();
+        writer.write_all(b"GET /index").await.unwrap();
+
+        let mut buf = [0; 7];
+        reader.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"GET /in");
+        reader.unread(b"/in").unwrap();
+        reader.unread(b"HEAD ").unwrap();
+        assert_eq!(reader.buffered_len().unwrap(), 11);
+
+        drop(writer);
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).await.unwrap();
+        assert_eq!(rest, b"HEAD /index");
+    }
+
+    #[tokio::test]
+    async fn unread_after_eof() {
+        let (writer, mut reader) = pipe();
+        drop(writer);
+        assert_eq!(reader.read(&mut [0; 4]).await.unwrap(), 0);
+
+        reader.unread(b"back").unwrap();
+        assert_eq!(reader.peek(&mut [0; 2]).await.unwrap(), 2);
+        let mut buf = [0; 8];
+        assert_eq!(reader.read(&mut buf).await.unwrap(), 4);
+        assert_eq!(&buf[..4], b"back");
+        assert_eq!(reader.read(&mut buf).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn split_off() {
+        let (mut writer, mut reader) = pipe();
+        writer.write_all(b"before ").await.unwrap();
+        let mut tail = reader.split_off();
+        writer.write_all(b"after").await.unwrap();
+        drop(writer);
+
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).await.unwrap();
+        assert_eq!(read, b"before after");
+        let mut read = Vec::new();
+        tail.read_to_end(&mut read).await.unwrap();
+        assert_eq!(read, b"after");
+    }
+
+    #[tokio::test]
+    async fn split_off_waits_for_the_slower_reader() {
+        let (mut writer, mut reader) = pipe_with_capacity(4);
+        let mut tail = reader.split_off();
+        writer.write_all(b"full").await.unwrap();
+
+        reader.read_exact(&mut [0; 4]).await.unwrap();
+        assert_eq!(writer.available().unwrap(), 0);
+        tail.read_exact(&mut [0; 2]).await.unwrap();
+        assert_eq!(writer.available().unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn reader_close_wakes_the_other_readers() {
+        let (_writer, mut readers) = broadcast(2);
+        let closing = readers.pop().unwrap();
+        let mut waiting = readers.pop().unwrap();
+
+        let read = tokio::spawn(async move { waiting.read(&mut [0; 4]).await.unwrap() });
+        tokio::task::yield_now().await;
+        closing.close().unwrap();
+
+        let read = tokio::time::timeout(Duration::from_secs(1), read).await;
+        assert_eq!(read.unwrap().unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn write_all_vectored() {
+        let (mut writer, mut reader) = pipe_with_capacity(8);
+        let read = tokio::spawn(async move {
+            let mut read = Vec::new();
+            reader.read_to_end(&mut read).await.unwrap();
+            read
+        });
+
+        let mut bufs = [
+            IoSlice::new(b""),
+            IoSlice::new(b"hello "),
+            IoSlice::new(b""),
+            IoSlice::new(b"vectored "),
+            IoSlice::new(b"world"),
+        ];
+        writer.write_all_vectored(&mut bufs).await.unwrap();
+        drop(writer);
+
+        assert_eq!(read.await.unwrap(), b"hello vectored world");
+    }
+
+    #[tokio::test]
+    async fn stats() {
+        let (mut writer, mut reader) = pipe();
+        let _clone = writer.clone();
+        writer.write_all(b"0123456789").await.unwrap();
+        reader.read_exact(&mut [0; 4]).await.unwrap();
+
+        let stats = PipeStats {
+            bytes_written: 10,
+            bytes_read: 4,
+            buffered: 6,
+            closed: false,
+            reader_count: 1,
+            writer_count: 2,
+        };
+        assert_eq!(writer.stats().unwrap(), stats);
+        assert_eq!(reader.stats().unwrap(), stats);
+
+        writer.close().unwrap();
+        let stats = reader.stats().unwrap();
+        assert!(stats.closed);
+        assert_eq!(
+            stats.bytes_written - stats.bytes_read,
+            stats.buffered as u64
+        );
+    }
+
+    #[tokio::test]
+    async fn stats_of_broadcast_count_bytes_read_by_every_reader() {
+        let (mut writer, mut readers) = broadcast(2);
+        writer.write_all(b"0123456789").await.unwrap();
+        readers[0].read_exact(&mut [0; 10]).await.unwrap();
+        readers[1].read_exact(&mut [0; 4]).await.unwrap();
+
+        let stats = writer.stats().unwrap();
+        assert_eq!(
+            (stats.bytes_written, stats.bytes_read, stats.buffered),
+            (10, 4, 6)
+        );
+        assert_eq!(readers[0].bytes_read().unwrap(), 10);
+
+        drop(readers.remove(1));
+        let stats = writer.stats().unwrap();
+        assert_eq!(
+            (stats.bytes_written, stats.bytes_read, stats.buffered),
+            (10, 10, 0)
+        );
+    }
+
+    #[tokio::test]
+    async fn stats_dont_count_discarded_or_preloaded_bytes() {
+        let (mut writer, mut reader) = pipe_from(*b"pre");
+        writer.write_all(b"0123456789").await.unwrap();
+        reader.read_exact(&mut [0; 8]).await.unwrap();
+        assert_eq!(writer.close_discarding().unwrap(), 5);
+
+        let stats = reader.stats().unwrap();
+        assert_eq!(
+            (stats.bytes_written, stats.bytes_read, stats.buffered),
+            (10, 8, 0)
+        );
+        assert_eq!(stats.bytes_written, writer.bytes_written().unwrap());
+        assert_eq!(stats.bytes_read, reader.bytes_read().unwrap());
+
+        reader.unread(b"back").unwrap();
+        reader.read_exact(&mut [0; 4]).await.unwrap();
+        assert_eq!(reader.stats().unwrap().bytes_read, 8);
+        assert_eq!(reader.bytes_read().unwrap(), 12);
+    }
+
+    #[tokio::test]
+    async fn fill_buf_follows_other_reads() {
+        let (mut writer, mut reader) = pipe_with_capacity(4096);
+        writer.write_all(&[1; 3000]).await.unwrap();
+        writer.write_all(b"hello world").await.unwrap();
+
+        assert_eq!(reader.fill_buf().await.unwrap().len(), 1024);
+        reader.read_exact(&mut [0; 3000]).await.unwrap();
+        assert_eq!(reader.fill_buf().await.unwrap(), b"hello world");
+        reader.read_exact(&mut [0; 6]).await.unwrap();
+        assert_eq!(reader.fill_buf().await.unwrap(), b"world");
+        reader.unread(b"x").unwrap();
+        assert_eq!(reader.fill_buf().await.unwrap(), b"x");
+        Pin::new(&mut reader).consume(1);
+        assert_eq!(reader.fill_buf().await.unwrap(), b"world");
+    }
+
+    #[tokio::test]
+    async fn consume_after_discard() {
+        let (mut writer, mut reader) = pipe();
+        let mut tail = reader.split_off();
+        writer.write_all(b"hello").await.unwrap();
+
+        assert_eq!(reader.fill_buf().await.unwrap(), b"hello");
+        assert_eq!(writer.close_discarding().unwrap(), 5);
+        Pin::new(&mut reader).consume(5);
+
+        assert_eq!(reader.buffered_len().unwrap(), 0);
+        assert_eq!(tail.buffered_len().unwrap(), 0);
+        assert_eq!(reader.bytes_read().unwrap(), 0);
+        assert_eq!(writer.bytes_written().unwrap(), 5);
+        assert_eq!(reader.read(&mut [0; 4]).await.unwrap(), 0);
+        assert_eq!(tail.read(&mut [0; 4]).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn on_close_runs_once_the_pipe_is_unlocked() {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let weak = Arc::new(std::sync::Mutex::new(None::<super::WeakPipeWriter>));
+        let (writer, reader) = {
+            let weak = Arc::clone(&weak);
+            PipeBuilder::new()
+                .on_close(move |stats| {
+                    // Both calls lock the pipe.
+                    let writer = weak.lock().unwrap().take().unwrap().upgrade().unwrap();
+                    tx.send((stats.reason, writer.is_closed().unwrap()))
+                        .unwrap();
+                })
+                .build()
+        };
+        *weak.lock().unwrap() = Some(writer.downgrade());
+
+        reader.close().unwrap();
+        assert_eq!(rx.await.unwrap(), (EofReason::Aborted, true));
+    }
+
+    #[tokio::test]
+    async fn flush_fails_once_the_reader_closes() {
+        let (mut writer, reader) = pipe();
+        writer.write_all(b"undelivered").await.unwrap();
+
+        let flush = tokio::spawn(async move { writer.flush().await });
+        tokio::task::yield_now().await;
+        reader.close().unwrap();
+
+        let flush = tokio::time::timeout(Duration::from_secs(1), flush).await;
+        let io_error = flush.unwrap().unwrap().unwrap_err();
+        assert_eq!(io_error.kind(), io::ErrorKind::BrokenPipe);
+    }
+}
+
+#[cfg(all(test, feature = "futures"))]
+mod futures_test {
+    use super::pipe_with_capacity;
+    use futures::executor::block_on;
+    use futures::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt};
+    use std::thread;
+
+    #[test]
+    fn read_write() {
+        let (mut writer, mut reader) = pipe_with_capacity(4);
+
+        let write = thread::spawn(move || {
+            block_on(async {
+                writer.write_all(b"hello world").await.unwrap();
+                AsyncWriteExt::close(&mut writer).await.unwrap();
+            })
+        });
+
+        let mut buf = Vec::new();
+        block_on(reader.read_to_end(&mut buf)).unwrap();
+        assert_eq!(buf, b"hello world");
+        write.join().unwrap();
+    }
+
+    #[test]
+    fn read_line() {
+        let (mut writer, reader) = pipe_with_capacity(4);
+
+        let write = thread::spawn(move || block_on(writer.write_all(b"hello\nworld")).unwrap());
+
+        let mut lines = Vec::new();
+        let mut reader = futures::io::BufReader::new(reader);
+        block_on(async {
+            let mut line = String::new();
+            while reader.read_line(&mut line).await.unwrap() > 0 {
+                lines.push(line.clone());
+                line.clear();
+            }
+        });
+        assert_eq!(lines, ["hello\n", "world"]);
+        write.join().unwrap();
+    }
+
+    #[test]
+    fn read_records() {
+        use futures::stream::TryStreamExt;
+
+        let (mut writer, mut reader) = pipe_with_capacity(4);
+
+        let write = thread::spawn(move || block_on(writer.write_all(b"a,b\nc,d\ne")).unwrap());
+
+        block_on(async {
+            let mut record = Vec::new();
+            reader.read_until(b'\n', &mut record).await.unwrap();
+            assert_eq!(record, b"a,b\n");
+
+            let lines: Vec<String> = reader.lines().try_collect().await.unwrap();
+            assert_eq!(lines, ["c,d", "e"]);
+        });
+        write.join().unwrap();
+    }
+
+    #[test]
+    fn read_vectored() {
+        use std::io::IoSliceMut;
+
+        let (mut writer, mut reader) = pipe_with_capacity(16);
+        block_on(writer.write_all(b"hello world")).unwrap();
+
+        let (mut first, mut second) = ([0; 4], [0; 4]);
+        let mut bufs = [IoSliceMut::new(&mut first), IoSliceMut::new(&mut second)];
+        assert_eq!(block_on(reader.read_vectored(&mut bufs)).unwrap(), 8);
+        assert_eq!(&first, b"hell");
+        assert_eq!(&second, b"o wo");
+
+        let (mut first, mut second) = ([0; 8], [0; 8]);
+        let mut bufs = [IoSliceMut::new(&mut first), IoSliceMut::new(&mut second)];
+        assert_eq!(block_on(reader.read_vectored(&mut bufs)).unwrap(), 3);
+        assert_eq!(&first[..3], b"rld");
+    }
+
+    // The tokio timer takes over when both features are enabled, it needs a tokio runtime.
+    #[cfg(not(feature = "tokio"))]
+    #[test]
+    fn read_timeout() {
+        use std::time::Duration;
+
+        let (mut writer, mut reader) = pipe_with_capacity(4);
+        let mut buf = [0; 4];
+
+        let err = block_on(reader.read_timeout(&mut buf, Duration::from_millis(10))).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+
+        block_on(writer.write_all(b"hi")).unwrap();
+        let n = block_on(reader.read_timeout(&mut buf, Duration::from_secs(1))).unwrap();
+        assert_eq!(&buf[..n], b"hi");
+    }
+
+    #[cfg(not(feature = "tokio"))]
+    #[test]
+    fn throttle_writer() {
+        use std::time::{Duration, Instant};
+
+        let (writer, mut reader) = pipe_with_capacity(64);
+        let mut writer = writer.throttle(1000);
+
+        let start = Instant::now();
+        let write = thread::spawn(move || block_on(writer.write_all(&[1; 50])).unwrap());
+        let mut buf = [0; 50];
+        block_on(reader.read_exact(&mut buf)).unwrap();
+        write.join().unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+
+    #[test]
+    fn by_ref_take() {
+        let (mut writer, mut reader) = pipe_with_capacity(16);
+        block_on(async {
+            writer.write_all(b"headerbody").await.unwrap();
+            drop(writer);
+
+            let mut header = Vec::new();
+            reader
+                .by_ref()
+                .take(6)
+                .read_to_end(&mut header)
+                .await
+                .unwrap();
+            assert_eq!(header, b"header");
+
+            let mut body = Vec::new();
+            reader.read_to_end(&mut body).await.unwrap();
+            assert_eq!(body, b"body");
+        });
+    }
+
+    #[test]
+    fn write_after_close_fails() {
+        let (mut writer, _reader) = pipe_with_capacity(16);
+        block_on(async {
+            AsyncWriteExt::close(&mut writer).await.unwrap();
+            let io_error = writer.write(b"late").await.unwrap_err();
+            assert_eq!(io_error.kind(), std::io::ErrorKind::BrokenPipe);
+        });
+    }
+
+    #[test]
+    fn write_vectored() {
+        use std::io::IoSlice;
+
+        let (mut writer, mut reader) = pipe_with_capacity(16);
+        block_on(async {
+            let bufs = [
+                IoSlice::new(b"hello"),
+                IoSlice::new(b" "),
+                IoSlice::new(b"world"),
+            ];
+            assert_eq!(writer.write_vectored(&bufs).await.unwrap(), 11);
+            drop(writer);
+
+            let mut read = Vec::new();
+            reader.read_to_end(&mut read).await.unwrap();
+            assert_eq!(read, b"hello world");
+        });
+    }
+
+    #[test]
+    fn boxed_trait_objects() {
+        let (writer, reader) = pipe_with_capacity(16);
+        let mut writer: Box<dyn futures::io::AsyncWrite + Unpin + Send> = writer.into_dyn();
+        let mut reader: Box<dyn futures::io::AsyncRead + Unpin + Send> = reader.into_dyn();
+        block_on(async {
+            writer.write_all(b"hi").await.unwrap();
+            drop(writer);
+            let mut buf = Vec::new();
+            reader.read_to_end(&mut buf).await.unwrap();
+            assert_eq!(buf, b"hi");
+        });
+    }
+}
+
+#[cfg(test)]
+mod poll_test {
+    use super::{pipe, pipe_with_capacity, PipeReader, PipeWriter};
+    use std::io;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+
+    struct Task {
+        wakes: Arc<CountWakes>,
+        waker: Waker,
+    }
+
+    struct CountWakes(AtomicUsize);
+
+    impl Wake for CountWakes {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    impl Task {
+        fn new() -> Task {
+            let wakes = Arc::new(CountWakes(AtomicUsize::new(0)));
+            let waker = Waker::from(Arc::clone(&wakes));
+            Task { wakes, waker }
+        }
+
+        fn wakes(&self) -> usize {
+            self.wakes.0.load(Ordering::SeqCst)
+        }
+
+        fn read(&self, reader: &mut PipeReader, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+            Pin::new(reader).poll_read_impl(&mut Context::from_waker(&self.waker), buf)
+        }
+
+        fn write(&self, writer: &mut PipeWriter, buf: &[u8]) -> Poll<io::Result<usize>> {
+            Pin::new(writer).poll_write_impl(&mut Context::from_waker(&self.waker), buf)
+        }
+    }
+
+    #[test]
+    fn write_then_immediate_drop() {
+        let (reader_task, writer_task) = (Task::new(), Task::new());
+        let (mut writer, mut reader) = pipe();
+        let mut buf = [0; 8];
+
+        assert!(reader_task.read(&mut reader, &mut buf).is_pending());
+        assert!(matches!(
+            writer_task.write(&mut writer, b"last"),
+            Poll::Ready(Ok(4))
+        ));
+        drop(writer);
+        assert_eq!(reader_task.wakes(), 1);
+
+        assert!(matches!(
+            reader_task.read(&mut reader, &mut buf),
+            Poll::Ready(Ok(4))
+        ));
+        assert_eq!(&buf[..4], b"last");
+        assert!(matches!(
+            reader_task.read(&mut reader, &mut buf),
+            Poll::Ready(Ok(0))
+        ));
+    }
+
+    #[test]
+    fn close_wakes_a_pending_reader() {
+        let reader_task = Task::new();
+        let (writer, mut reader) = pipe();
+
+        assert!(reader_task.read(&mut reader, &mut [0; 8]).is_pending());
+        writer.close().unwrap();
+
+        assert_eq!(reader_task.wakes(), 1);
+        assert!(matches!(
+            reader_task.read(&mut reader, &mut [0; 8]),
+            Poll::Ready(Ok(0))
+        ));
+    }
+
+    #[test]
+    fn reader_close_wakes_a_pending_writer() {
+        let writer_task = Task::new();
+        let (mut writer, reader) = pipe_with_capacity(4);
+
+        assert!(matches!(
+            writer_task.write(&mut writer, b"full"),
+            Poll::Ready(Ok(4))
+        ));
+        assert!(writer_task.write(&mut writer, b"more").is_pending());
+        reader.close().unwrap();
+
+        assert_eq!(writer_task.wakes(), 1);
+        match writer_task.write(&mut writer, b"more") {
+            Poll::Ready(Err(err)) => assert_eq!(err.kind(), io::ErrorKind::BrokenPipe),
+            poll => panic!("unexpected {:?}", poll),
+        }
+    }
+
+    #[test]
+    fn concurrent_close_from_both_halves() {
+        let (reader_task, writer_task) = (Task::new(), Task::new());
+        let (mut writer, mut reader) = pipe_with_capacity(4);
+
+        assert!(matches!(
+            writer_task.write(&mut writer, b"full"),
+            Poll::Ready(Ok(4))
+        ));
+        assert!(writer_task.write(&mut writer, b"more").is_pending());
+        writer.close().unwrap();
+        reader.close().unwrap();
+
+        assert_eq!(writer_task.wakes(), 1);
+        // Closing keeps the buffered bytes readable, whichever half closed first.
+        assert!(matches!(
+            reader_task.read(&mut reader, &mut [0; 8]),
+            Poll::Ready(Ok(4))
+        ));
+        assert!(matches!(
+            reader_task.read(&mut reader, &mut [0; 8]),
+            Poll::Ready(Ok(0))
+        ));
+        assert!(matches!(
+            writer_task.write(&mut writer, b"more"),
+            Poll::Ready(Err(_))
+        ));
+    }
+
+    #[test]
+    fn partial_reads() {
+        let reader_task = Task::new();
+        let (mut writer, mut reader) = pipe();
+        let mut buf = [0; 4];
+
+        assert!(matches!(
+            Task::new().write(&mut writer, b"0123456789"),
+            Poll::Ready(Ok(10))
+        ));
+        assert!(matches!(
+            reader_task.read(&mut reader, &mut buf[..3]),
+            Poll::Ready(Ok(3))
+        ));
+        assert_eq!(&buf[..3], b"012");
+        assert!(matches!(
+            reader_task.read(&mut reader, &mut buf[..3]),
+            Poll::Ready(Ok(3))
+        ));
+        assert_eq!(&buf[..3], b"345");
+        assert!(matches!(
+            reader_task.read(&mut reader, &mut buf),
+            Poll::Ready(Ok(4))
+        ));
+        assert_eq!(&buf, b"6789");
+        assert!(reader_task.read(&mut reader, &mut buf).is_pending());
+        assert_eq!(reader_task.wakes(), 0);
+    }
+
+    #[test]
+    fn partial_read_wakes_a_writer_waiting_on_a_full_pipe() {
+        let (reader_task, writer_task) = (Task::new(), Task::new());
+        let (mut writer, mut reader) = pipe_with_capacity(4);
+
+        assert!(matches!(
+            writer_task.write(&mut writer, b"abcdef"),
+            Poll::Ready(Ok(4))
+        ));
+        assert!(writer_task.write(&mut writer, b"ef").is_pending());
+        assert!(matches!(
+            reader_task.read(&mut reader, &mut [0; 1]),
+            Poll::Ready(Ok(1))
+        ));
+
+        assert_eq!(writer_task.wakes(), 1);
+        assert!(matches!(
+            writer_task.write(&mut writer, b"ef"),
+            Poll::Ready(Ok(1))
+        ));
+        assert!(writer_task.write(&mut writer, b"f").is_pending());
+    }
+
+    #[test]
+    fn reader_drop_wakes_a_pending_writer() {
+        let writer_task = Task::new();
+        let (mut writer, reader) = pipe_with_capacity(4);
+
+        assert!(matches!(
+            writer_task.write(&mut writer, b"full"),
+            Poll::Ready(Ok(4))
+        ));
+        assert!(writer_task.write(&mut writer, b"more").is_pending());
+        drop(reader);
+
+        assert_eq!(writer_task.wakes(), 1);
+        assert!(matches!(
+            writer_task.write(&mut writer, b"more"),
+            Poll::Ready(Err(_))
+        ));
+    }
 }
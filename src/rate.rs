@@ -0,0 +1,63 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+// tokio's clock is the std one, except that tests can pause it.
+#[cfg(all(
+    not(feature = "tokio"),
+    not(all(target_arch = "wasm32", target_os = "unknown"))
+))]
+use std::time::Instant;
+#[cfg(feature = "tokio")]
+use tokio::time::Instant;
+#[cfg(all(not(feature = "tokio"), target_arch = "wasm32", target_os = "unknown"))]
+use web_time::Instant;
+
+/// The span of time the throughput is averaged over.
+const WINDOW: Duration = Duration::from_secs(1);
+
+/// A throughput estimate over the last second, enabled by `PipeBuilder::with_rate_tracking`.
+pub(crate) struct Rate {
+    started: Instant,
+    // The number of bytes transferred at each point in time within the window, oldest first.
+    samples: VecDeque<(Instant, u64)>,
+    total: u64,
+}
+
+impl Rate {
+    pub(crate) fn new() -> Rate {
+        Rate {
+            started: Instant::now(),
+            samples: VecDeque::new(),
+            total: 0,
+        }
+    }
+
+    pub(crate) fn record(&mut self, bytes: usize) {
+        let now = Instant::now();
+        self.samples.push_back((now, bytes as u64));
+        self.total += bytes as u64;
+        self.expire(now);
+    }
+
+    /// Returns the bytes per second over the window, or since tracking started if that is shorter.
+    pub(crate) fn per_sec(&mut self) -> f64 {
+        let now = Instant::now();
+        self.expire(now);
+
+        let span = now.duration_since(self.started).min(WINDOW).as_secs_f64();
+        if span == 0.0 {
+            0.0
+        } else {
+            self.total as f64 / span
+        }
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some(&(at, bytes)) = self.samples.front() {
+            if now.duration_since(at) <= WINDOW {
+                break;
+            }
+            self.samples.pop_front();
+            self.total -= bytes;
+        }
+    }
+}
@@ -1,103 +1,692 @@
-use crate::state::State;
-use std::io;
+use crate::error::PipeError;
+use crate::state::{lock, register_waker, try_lock, unlock, Mutex, State};
+#[cfg(any(feature = "tokio", feature = "futures"))]
+use crate::state::{ReadCursor, BUFFER_SIZE};
+use crate::stats::PipeStats;
+use std::fmt;
+use std::io::{self, IoSliceMut};
 use std::pin::Pin;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 use std::task::{Context, Poll};
+#[cfg(any(feature = "tokio", feature = "futures"))]
+use std::time::Duration;
+
+/// Why a [`PipeReader`](struct.PipeReader.html) reached EOF, see
+/// [`PipeReader::eof_reason`](struct.PipeReader.html#method.eof_reason).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EofReason {
+    /// The writers are done: every writer was shut down through `AsyncWrite` or dropped.
+    WriterFinished,
+    /// The pipe was closed before the writers were done, by `close` on either half, by dropping
+    /// every reader or by a cancellation token. The bytes read may be truncated.
+    Aborted,
+}
 
 /// The read half of the pipe
 ///
-/// Implements [`tokio::io::AsyncRead`][tokio-async-read] when feature `tokio` is enabled (the
-/// default). Implements [`futures::io::AsyncRead`][futures-async-read] when feature `futures` is
-/// enabled.
+/// Implements [`tokio::io::AsyncRead`][tokio-async-read] and
+/// [`tokio::io::AsyncBufRead`][tokio-async-buf-read] when feature `tokio` is enabled (the
+/// default). Implements [`futures::io::AsyncRead`][futures-async-read] and
+/// [`futures::io::AsyncBufRead`][futures-async-buf-read] when feature `futures` is enabled.
+///
+/// Reads are cancel safe: a read that returns `Pending` hasn't taken any bytes, so dropping its
+/// future, for example in a `select!`, loses nothing and the next read gets the same bytes. The
+/// waker it left behind is replaced by the next poll, until then a write only wakes its task
+/// spuriously.
 ///
 /// [futures-async-read]: https://docs.rs/futures/0.3.5/futures/io/trait.AsyncRead.html
-/// [tokio-async-read]: https://docs.rs/tokio/0.2.16/tokio/io/trait.AsyncRead.html
+/// [futures-async-buf-read]: https://docs.rs/futures/0.3.5/futures/io/trait.AsyncBufRead.html
+/// [tokio-async-read]: https://docs.rs/tokio/1/tokio/io/trait.AsyncRead.html
+/// [tokio-async-buf-read]: https://docs.rs/tokio/1/tokio/io/trait.AsyncBufRead.html
 pub struct PipeReader {
     pub(crate) state: Arc<Mutex<State>>,
+    pub(crate) id: usize,
+    // A copy of the front contiguous run of unread bytes, handed out by `poll_fill_buf`, at most
+    // `BUFFER_SIZE` of them. The shared buffer stays the source of truth: the copy is only handed
+    // out again while the reader is still at `fill_at`, any other read, `unread` or discard moves
+    // it.
+    #[cfg(any(feature = "tokio", feature = "futures"))]
+    fill_buf: Vec<u8>,
+    #[cfg(any(feature = "tokio", feature = "futures"))]
+    fill_pos: usize,
+    #[cfg(any(feature = "tokio", feature = "futures"))]
+    fill_at: Option<ReadCursor>,
 }
 
 impl PipeReader {
+    /// Registers a new reader on `state`, it reads from the front of the buffer.
+    pub(crate) fn new(state: Arc<Mutex<State>>) -> PipeReader {
+        let id = lock(&state).add_reader();
+
+        PipeReader::with_id(state, id)
+    }
+
+    fn with_id(state: Arc<Mutex<State>>, id: usize) -> PipeReader {
+        PipeReader {
+            state,
+            id,
+            #[cfg(any(feature = "tokio", feature = "futures"))]
+            fill_buf: Vec::new(),
+            #[cfg(any(feature = "tokio", feature = "futures"))]
+            fill_pos: 0,
+            #[cfg(any(feature = "tokio", feature = "futures"))]
+            fill_at: None,
+        }
+    }
+
+    /// Creates a second reader of the same pipe, receiving every byte written from now on.
+    ///
+    /// The bytes already buffered, including pushed back ones, stay with `self` only, the new
+    /// reader starts with nothing to read and sees just the bytes written after the call. Like with [`broadcast`](fn.broadcast.html), a written byte counts against the
+    /// capacity until both readers have read it, so the writers wait for the slower one.
+    pub fn split_off(&self) -> PipeReader {
+        let id = {
+            let mut state = lock(&self.state);
+            let end = state.buffer.end();
+            state.add_reader_at(end)
+        };
+
+        PipeReader::with_id(Arc::clone(&self.state), id)
+    }
+
     /// Closes the pipe, any further read will return EOF and any further write will raise an error.
-    pub fn close(&self) -> io::Result<()> {
-        match self.state.lock() {
-            Ok(mut state) => {
-                state.closed = true;
-                self.wake_writer_half(&*state);
-                Ok(())
+    pub fn close(&self) -> Result<(), PipeError> {
+        self.close_with(io::ErrorKind::BrokenPipe)
+    }
+
+    /// Closes the pipe like [`close`](#method.close), but further writes fail with an error of the
+    /// given `kind` instead of `BrokenPipe`.
+    pub fn close_with(&self, kind: io::ErrorKind) -> Result<(), PipeError> {
+        let mut state = lock(&self.state);
+
+        state.mark_closed(EofReason::Aborted);
+        state.closed_by_reader = true;
+        state.close_kind = kind;
+        self.wake_writer_half(&mut state);
+        // The other readers of a broadcast or a split off reader can be waiting for data too.
+        state.wake_readers();
+        trace!(state, kind = ?kind, "close");
+        unlock(state);
+        Ok(())
+    }
+
+    /// Reopens a closed pipe so the same pair of halves can be used again.
+    ///
+    /// Fails if there is unread data, if a read or write is still pending, or if the other half is
+    /// gone.
+    pub fn reset(&self) -> io::Result<()> {
+        let mut state = lock(&self.state);
+
+        state.reset().map_err(|reason| {
+            io::Error::other(format!(
+                "{}: {}: Failed to reset the channel: {}",
+                env!("CARGO_PKG_NAME"),
+                state.describe("PipeReader"),
+                reason
+            ))
+        })
+    }
+
+    /// Returns true once the pipe is closed, either explicitly or because every reader or every
+    /// writer is gone.
+    ///
+    /// Never fails, the `Result` is kept from when a poisoned lock was reported as
+    /// [`PipeError::Poisoned`](enum.PipeError.html#variant.Poisoned), so callers keep compiling.
+    pub fn is_closed(&self) -> Result<bool, PipeError> {
+        let state = lock(&self.state);
+
+        Ok(state.closed)
+    }
+
+    /// It returns true if the next data chunk is written by the writer and consumed by the reader; Otherwise it returns false.
+    ///
+    /// Never fails, like [`is_closed`](#method.is_closed).
+    pub fn is_flushed(&self) -> Result<bool, PipeError> {
+        let state = lock(&self.state);
+
+        Ok(state.unread_len(self.id) == 0)
+    }
+
+    /// Returns the number of bytes that can be read right away without waiting for the writer.
+    ///
+    /// Never fails, like [`is_closed`](#method.is_closed).
+    pub fn buffered_len(&self) -> Result<usize, PipeError> {
+        let state = lock(&self.state);
+
+        Ok(state.unread_len(self.id))
+    }
+
+    /// Returns bounds on the number of bytes left to read, to preallocate for `read_to_end`.
+    ///
+    /// The lower bound is the number of bytes buffered for this reader. The upper bound is known
+    /// once the pipe is closed, or if a [`content_length`](struct.PipeBuilder.html#method.content_length)
+    /// was set.
+    pub fn size_hint(&self) -> (usize, Option<usize>) {
+        let state = lock(&self.state);
+        let buffered = state.unread_len(self.id);
+
+        if state.closed {
+            return (buffered, Some(buffered));
+        }
+        let upper = state.content_length.map(|len| {
+            // Positions count the bytes written since the pipe was created.
+            let left = len.saturating_sub(state.reader(self.id).pos as u64);
+            (left as usize).max(buffered)
+        });
+        (buffered, upper)
+    }
+
+    /// Returns the total number of bytes read through this reader.
+    ///
+    /// The count only grows, it is kept when the pipe is [`reset`](#method.reset).
+    pub fn bytes_read(&self) -> Result<u64, PipeError> {
+        let state = lock(&self.state);
+
+        Ok(state.reader(self.id).bytes_read)
+    }
+
+    /// Returns the number of bytes per second read from the pipe over the last second, by all of
+    /// its readers.
+    ///
+    /// Returns `None` unless the pipe was built with
+    /// [`with_rate_tracking`](struct.PipeBuilder.html#method.with_rate_tracking).
+    pub fn bytes_per_sec(&self) -> Option<f64> {
+        let mut state = lock(&self.state);
+
+        state.read_rate.as_mut().map(|rate| rate.per_sec())
+    }
+
+    /// Returns the number of live readers of the pipe.
+    ///
+    /// Only the handles themselves are counted, wrappers like `TakeReader` hold a single one.
+    pub fn reader_count(&self) -> Result<usize, PipeError> {
+        let state = lock(&self.state);
+
+        Ok(state.readers.len())
+    }
+
+    /// Returns the number of live writers of the pipe.
+    pub fn writer_count(&self) -> Result<usize, PipeError> {
+        let state = lock(&self.state);
+
+        Ok(state.writers.len())
+    }
+
+    /// Returns the counters of the pipe, all taken at the same time.
+    ///
+    /// Never fails, like [`is_closed`](#method.is_closed).
+    pub fn stats(&self) -> Result<PipeStats, PipeError> {
+        let state = lock(&self.state);
+
+        Ok(state.stats())
+    }
+
+    /// Consumes the reader, returning the bytes that were written but not read yet.
+    pub fn into_inner(self) -> io::Result<Vec<u8>> {
+        let state = lock(&self.state);
+
+        let mut unread = Vec::with_capacity(state.unread_len(self.id));
+        for slice in state.unread_slices(self.id) {
+            unread.extend_from_slice(slice);
+        }
+        Ok(unread)
+    }
+
+    /// Copies the next unread bytes into `buf` without consuming them, so a following read returns
+    /// them again.
+    ///
+    /// Waits for data like a regular read if nothing is buffered yet, and returns `Ok(0)` at EOF.
+    pub async fn peek(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        std::future::poll_fn(|cx| self.poll_peek(cx, buf)).await
+    }
+
+    /// Attempts to peek into `buf`, the poll behind [`peek`](#method.peek).
+    ///
+    /// For hand written state machines, such as sniffing a header before deciding how to read
+    /// the rest. Follows the rules of [`poll_read_impl`](#method.poll_read_impl), except that the
+    /// bytes are left in the pipe: `Pending` registers the waker of `cx` to be woken once there
+    /// is data.
+    pub fn poll_peek(&self, cx: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        let mut state = lock(&self.state);
+
+        if state.unread_len(self.id) == 0 {
+            if state.closed {
+                Poll::Ready(Ok(0))
+            } else {
+                self.wake_writer_half(&mut state);
+                let reader = state.reader_mut(self.id);
+                reader.streak = 0;
+                register_waker(&mut reader.waker, cx.waker());
+                Poll::Pending
             }
-            Err(err) => Err(io::Error::new(
-                io::ErrorKind::Other,
-                format!(
-                    "{}: PipeReader: Failed to lock the channel state: {}",
-                    env!("CARGO_PKG_NAME"),
-                    err
-                ),
-            )),
+        } else {
+            Poll::Ready(Ok(state.peek_unread(self.id, 0, buf)))
         }
     }
 
-    /// It returns true if the next data chunk is written by the writer and consumed by the reader; Otherwise it returns false.
-    pub fn is_flushed(&self) -> io::Result<bool> {
-        let state = match self.state.lock() {
-            Ok(s) => s,
-            Err(err) => {
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
+    /// Gives `bytes` back to the reader, the next reads return them before anything else.
+    ///
+    /// For speculative parsing: bytes read too early can be pushed back instead of being kept
+    /// aside. They don't have to be the bytes that were read, and they only go to this reader, not
+    /// to the other readers of a [`broadcast`](fn.broadcast.html). Pushed back bytes don't count
+    /// against the capacity of the pipe.
+    pub fn unread(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let mut state = lock(&self.state);
+        state
+            .reader_mut(self.id)
+            .pushback
+            .splice(..0, bytes.iter().copied());
+        trace!(state, bytes = bytes.len(), "unread");
+        Ok(())
+    }
+
+    /// Returns an id of the pipe, shared by all of its readers and writers.
+    ///
+    /// Halves of different pipes have different ids as long as both pipes are alive, an id may
+    /// be reused once every half of its pipe is dropped. Readers compare equal if their ids do.
+    pub fn pipe_id(&self) -> usize {
+        Arc::as_ptr(&self.state) as usize
+    }
+
+    /// Returns why the pipe was closed, once this reader has read everything and reached EOF.
+    ///
+    /// Returns `None` while the pipe is open or there are still bytes left for this reader. A
+    /// protocol that must not accept a truncated stream can check for
+    /// [`EofReason::Aborted`](enum.EofReason.html#variant.Aborted) after a read returned `Ok(0)`.
+    pub fn eof_reason(&self) -> Option<EofReason> {
+        let state = lock(&self.state);
+
+        if state.closed && state.unread_len(self.id) == 0 {
+            state.eof_reason
+        } else {
+            None
+        }
+    }
+
+    /// Borrows the reader, to apply an adapter that consumes its reader without giving this one
+    /// up.
+    ///
+    /// The adapters of this crate, like [`take`](#method.take), take a `PipeReader` by value, but
+    /// `&mut PipeReader` implements the `AsyncRead` traits as well, so the adapters of
+    /// `tokio::io::AsyncReadExt` and `futures::io::AsyncReadExt` work on the borrow:
+    /// `reader.by_ref().take(10)` reads up to 10 bytes and leaves the rest to `reader`.
+    pub fn by_ref(&mut self) -> &mut Self {
+        self
+    }
+
+    /// Boxes the reader as a trait object, to store readers of different types together.
+    ///
+    /// The trait object is `dyn tokio::io::AsyncRead + Unpin + Send` with feature `tokio`, and
+    /// `dyn futures::io::AsyncRead + Unpin + Send` with feature `futures`. With both features
+    /// enabled, the type the result is assigned to picks the one.
+    #[cfg(any(feature = "tokio", feature = "futures"))]
+    pub fn into_dyn<T: ?Sized>(self) -> Box<T>
+    where
+        Box<T>: From<PipeReader>,
+    {
+        self.into()
+    }
+
+    /// Reads until EOF and returns all the bytes read.
+    ///
+    /// Unlike `read_to_end` it copies everything buffered straight into the `Vec` on each wake up,
+    /// growing it once for the whole batch instead of reading it in small pieces.
+    pub async fn drain_to_vec(&mut self) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        loop {
+            let n = std::future::poll_fn(|cx| {
+                Pin::new(&mut *self).poll_read_with(cx, |state, id| {
+                    let len = out.len();
+                    out.reserve(state.unread_len(id));
+                    for slice in state.unread_slices(id) {
+                        out.extend_from_slice(slice);
+                    }
+                    out.len() - len
+                })
+            })
+            .await?;
+            if n == 0 {
+                return Ok(out);
+            }
+        }
+    }
+
+    /// Reads into `buf`, a `bytes::BufMut` such as `BytesMut`, without going through a `&mut [u8]`.
+    ///
+    /// Copies as many of the buffered bytes as `buf` has room for, which for a `BytesMut` is all
+    /// of them. Returns the number of bytes read, `Ok(0)` at EOF or if `buf` is full.
+    #[cfg(feature = "bytes")]
+    pub async fn read_buf<B: bytes::BufMut>(&mut self, buf: &mut B) -> io::Result<usize> {
+        if !buf.has_remaining_mut() {
+            return Ok(0);
+        }
+
+        std::future::poll_fn(|cx| {
+            Pin::new(&mut *self).poll_read_with(cx, |state, id| {
+                let mut copied = 0;
+                for slice in state.unread_slices(id) {
+                    let len = slice.len().min(buf.remaining_mut());
+                    buf.put_slice(&slice[..len]);
+                    copied += len;
+                    if !buf.has_remaining_mut() {
+                        break;
+                    }
+                }
+                copied
+            })
+        })
+        .await
+    }
+
+    /// Reads the next chunk of buffered bytes, `None` at EOF.
+    ///
+    /// A chunk handed over by [`PipeWriter::write_bytes`](struct.PipeWriter.html#method.write_bytes)
+    /// comes out as the same `Bytes`, without copying it, or what is left of it if part of it was
+    /// already read. Bytes written otherwise are copied into a new `Bytes`, as many as are
+    /// buffered next to each other.
+    #[cfg(feature = "bytes")]
+    pub async fn read_bytes(&mut self) -> io::Result<Option<bytes::Bytes>> {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_read_bytes(cx)).await
+    }
+
+    #[cfg(feature = "bytes")]
+    pub(crate) fn poll_read_bytes(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+    ) -> Poll<io::Result<Option<bytes::Bytes>>> {
+        let mut chunk = None;
+        let n = std::task::ready!(self.poll_read_with(cx, |state, id| {
+            let bytes = state.unread_bytes(id);
+            let len = bytes.len();
+            chunk = Some(bytes);
+            len
+        }))?;
+        Poll::Ready(Ok(chunk.filter(|_| n > 0)))
+    }
+
+    /// Fills `buf` like `read_exact`, but returns the number of bytes read if EOF comes first.
+    ///
+    /// Only returns fewer bytes than `buf.len()` at EOF. On an error the bytes read before it are in
+    /// `buf`, their number is lost.
+    pub async fn read_exact_or_eof(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            let n = std::future::poll_fn(|cx| {
+                Pin::new(&mut *self).poll_read_impl(cx, &mut buf[filled..])
+            })
+            .await?;
+            if n == 0 {
+                break;
+            }
+            filled += n;
+        }
+        Ok(filled)
+    }
+
+    /// Reads like a regular read, but fails with an error of kind `TimedOut` if no bytes arrive
+    /// within `dur`.
+    #[cfg(any(feature = "tokio", feature = "futures"))]
+    pub async fn read_timeout(&mut self, buf: &mut [u8], dur: Duration) -> io::Result<usize> {
+        use crate::time::{Instant, Sleep, Timer};
+        use std::future::Future;
+
+        let mut sleep = Box::pin(Sleep::at(Instant::now() + dur));
+        let read = std::future::poll_fn(|cx| {
+            if let Poll::Ready(result) = Pin::new(&mut *self).poll_read_impl(cx, buf) {
+                return Poll::Ready(Some(result));
+            }
+            sleep.as_mut().poll(cx).map(|()| None)
+        });
+        match read.await {
+            Some(result) => result,
+            None => {
+                // Forget the task that gave up, a later write has nobody to wake.
+                let mut state = lock(&self.state);
+                state.reader_mut(self.id).waker = None;
+                Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
                     format!(
-                        "{}: PipeReader: Failed to lock the channel state: {}",
+                        "{}: {}: No data arrived in time",
                         env!("CARGO_PKG_NAME"),
-                        err
+                        state.describe("PipeReader")
                     ),
-                ));
+                ))
             }
-        };
+        }
+    }
+
+    /// Reads the bytes available right away without waiting.
+    ///
+    /// Returns `Ok(0)` at EOF, and an error of kind `WouldBlock` if the pipe is open but no bytes
+    /// are buffered. Unlike a regular read, no waker is registered.
+    pub fn try_read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = lock(&self.state);
 
-        Ok(state.buffer.is_empty())
+        if state.unread_len(self.id) == 0 {
+            if state.closed {
+                Ok(0)
+            } else {
+                Err(io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    format!(
+                        "{}: {}: No data is available",
+                        env!("CARGO_PKG_NAME"),
+                        state.describe("PipeReader")
+                    ),
+                ))
+            }
+        } else {
+            let size_to_read = state.peek_unread(self.id, 0, buf);
+            state.advance_reader(self.id, size_to_read);
+            self.wake_writer_half(&mut state);
+
+            Ok(size_to_read)
+        }
     }
 
-    fn wake_writer_half(&self, state: &State) {
-        if let Some(ref waker) = state.writer_waker {
-            waker.clone().wake();
+    fn wake_writer_half(&self, state: &mut State) {
+        if state.read_wakes_writers() {
+            state.wake_writers();
         }
     }
 
-    fn poll_read(
+    /// Attempts to read into `buf`, the poll behind the `AsyncRead` impls.
+    ///
+    /// For driving the reader from a hand written `Future` without going through one of the
+    /// `AsyncRead` traits. All the read methods follow the same rules:
+    ///
+    /// * If `buf` is empty, returns `Ok(0)` right away without looking at the pipe, so it says
+    ///   nothing about EOF. Since the pipe is left alone, no waker is registered either.
+    /// * If there are bytes to read, copies as many as fit and returns their number.
+    /// * If there are none and the pipe is open, returns `Pending` after registering the waker of
+    ///   `cx` to be woken once there is data.
+    /// * If there are none and the pipe is closed, returns `Ok(0)`, which is EOF.
+    pub fn poll_read_impl(
         self: Pin<&mut Self>,
         cx: &mut Context,
         buf: &mut [u8],
     ) -> Poll<io::Result<usize>> {
-        let mut state = match self.state.lock() {
-            Ok(s) => s,
-            Err(err) => {
-                return Poll::Ready(Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    format!(
-                        "{}: PipeReader: Failed to lock the channel state: {}",
-                        env!("CARGO_PKG_NAME"),
-                        err
-                    ),
-                )))
+        self.poll_read_vectored(cx, &mut [IoSliceMut::new(buf)])
+    }
+
+    /// Reads at most `limit` bytes into the unfilled part of `buf`, returns how many were read.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn poll_read_buf(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut tokio::io::ReadBuf,
+        limit: usize,
+    ) -> Poll<io::Result<usize>> {
+        let max = buf.remaining().min(limit);
+        if max == 0 {
+            return Poll::Ready(Ok(0));
+        }
+
+        // `put_slice` copies into the unfilled part and marks it as initialized, so an
+        // uninitialized buffer is never zeroed first nor read from.
+        self.poll_read_with(cx, |state, id| {
+            let mut copied = 0;
+            for slice in state.unread_slices(id) {
+                let len = slice.len().min(max - copied);
+                buf.put_slice(&slice[..len]);
+                copied += len;
+                if copied == max {
+                    break;
+                }
             }
-        };
+            copied
+        })
+    }
 
-        if state.buffer.is_empty() {
-            if state.closed || Arc::strong_count(&self.state) == 1 {
+    /// Fills `bufs` in order, moving on to the next one only once the previous one is full.
+    fn poll_read_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        bufs: &mut [IoSliceMut],
+    ) -> Poll<io::Result<usize>> {
+        // Neither data nor EOF can be reported into empty buffers.
+        if bufs.iter().all(|buf| buf.is_empty()) {
+            return Poll::Ready(Ok(0));
+        }
+
+        self.poll_read_with(cx, |state, id| {
+            let mut copied = 0;
+            for buf in bufs.iter_mut() {
+                let n = state.peek_unread(id, copied, buf);
+                copied += n;
+                if n < buf.len() {
+                    break;
+                }
+            }
+            copied
+        })
+    }
+
+    // Waits for unread bytes, then lets `copy` copy as many of them as it takes from the unread
+    // bytes of the reader with the given id and returns how many it copied.
+    fn poll_read_with<F>(self: Pin<&mut Self>, cx: &mut Context, copy: F) -> Poll<io::Result<usize>>
+    where
+        F: FnOnce(&State, usize) -> usize,
+    {
+        let mut state = lock(&self.state);
+
+        if state.unread_len(self.id) == 0 {
+            if state.closed {
+                trace!(state, bytes = 0, poll = "ready", "poll_read");
                 Poll::Ready(Ok(0))
             } else {
-                self.wake_writer_half(&*state);
-                state.reader_waker = Some(cx.waker().clone());
+                self.wake_writer_half(&mut state);
+                let reader = state.reader_mut(self.id);
+                reader.streak = 0;
+                register_waker(&mut reader.waker, cx.waker());
+                trace!(state, poll = "pending", "poll_read");
                 Poll::Pending
             }
         } else {
-            self.wake_writer_half(&*state);
-            let size_to_read = state.buffer.len().min(buf.len());
-            let (to_read, rest) = state.buffer.split_at(size_to_read);
-            buf[..size_to_read].copy_from_slice(to_read);
-            state.buffer = rest.to_vec();
+            let budget = state.poll_budget;
+            if State::spend_budget(budget, &mut state.reader_mut(self.id).streak) {
+                cx.waker().wake_by_ref();
+                trace!(state, poll = "pending", reason = "budget", "poll_read");
+                return Poll::Pending;
+            }
+
+            let size_to_read = copy(&state, self.id);
+            state.advance_reader(self.id, size_to_read);
+            self.wake_writer_half(&mut state);
 
+            trace!(state, bytes = size_to_read, poll = "ready", "poll_read");
             Poll::Ready(Ok(size_to_read))
         }
     }
+
+    #[cfg(any(feature = "tokio", feature = "futures"))]
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        let mut state = lock(&this.state);
+
+        let cursor = state.read_cursor(this.id);
+        if this.fill_at != Some(cursor) || this.fill_pos >= this.fill_buf.len() {
+            if state.unread_len(this.id) == 0 {
+                return if state.closed {
+                    Poll::Ready(Ok(&[]))
+                } else {
+                    this.wake_writer_half(&mut state);
+                    register_waker(&mut state.reader_mut(this.id).waker, cx.waker());
+                    Poll::Pending
+                };
+            }
+
+            let front = state.unread_slices(this.id).next().unwrap_or(&[]);
+            this.fill_buf.clear();
+            this.fill_buf
+                .extend_from_slice(&front[..front.len().min(BUFFER_SIZE)]);
+            this.fill_pos = 0;
+            this.fill_at = Some(cursor);
+        }
+
+        Poll::Ready(Ok(&this.fill_buf[this.fill_pos..]))
+    }
+
+    #[cfg(any(feature = "tokio", feature = "futures"))]
+    fn consume(mut self: Pin<&mut Self>, amt: usize) {
+        let amt = amt.min(self.fill_buf.len() - self.fill_pos);
+        if amt == 0 {
+            return;
+        }
+
+        let mut state = lock(&self.state);
+        // Bytes handed out before the reader moved on were read some other way or discarded.
+        if self.fill_at != Some(state.read_cursor(self.id)) {
+            return;
+        }
+        state.advance_reader(self.id, amt);
+        self.wake_writer_half(&mut state);
+        let cursor = state.read_cursor(self.id);
+        drop(state);
+
+        self.fill_pos += amt;
+        self.fill_at = Some(cursor);
+    }
+}
+
+impl PartialEq for PipeReader {
+    /// Whether both handles belong to the same pipe, see [`pipe_id`](#method.pipe_id).
+    fn eq(&self, other: &PipeReader) -> bool {
+        Arc::ptr_eq(&self.state, &other.state)
+    }
+}
+
+impl Eq for PipeReader {}
+
+impl fmt::Debug for PipeReader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut d = f.debug_struct("PipeReader");
+        let state = match try_lock(&self.state) {
+            Some(state) => state,
+            None => return d.field("state", &format_args!("<locked>")).finish(),
+        };
+
+        if let Some(name) = &state.name {
+            d.field("name", name);
+        }
+        d.field("closed", &state.closed)
+            .field("buffered", &state.unread_len(self.id))
+            .field("waker", &state.reader(self.id).waker.is_some())
+            .finish()
+    }
+}
+
+impl Drop for PipeReader {
+    fn drop(&mut self) {
+        let mut state = lock(&self.state);
+        state.remove_reader(self.id);
+        self.wake_writer_half(&mut state);
+        unlock(state);
+    }
 }
 
 #[cfg(feature = "tokio")]
@@ -105,9 +694,9 @@ impl tokio::io::AsyncRead for PipeReader {
     fn poll_read(
         self: Pin<&mut Self>,
         cx: &mut Context,
-        buf: &mut [u8],
-    ) -> Poll<io::Result<usize>> {
-        self.poll_read(cx, buf)
+        buf: &mut tokio::io::ReadBuf,
+    ) -> Poll<io::Result<()>> {
+        self.poll_read_buf(cx, buf, usize::MAX).map_ok(|_| ())
     }
 }
 
@@ -118,6 +707,75 @@ impl futures::io::AsyncRead for PipeReader {
         cx: &mut Context,
         buf: &mut [u8],
     ) -> Poll<io::Result<usize>> {
-        self.poll_read(cx, buf)
+        self.poll_read_impl(cx, buf)
+    }
+
+    fn poll_read_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        bufs: &mut [IoSliceMut],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_read_vectored(cx, bufs)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl tokio::io::AsyncBufRead for PipeReader {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<&[u8]>> {
+        self.poll_fill_buf(cx)
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        self.consume(amt)
+    }
+}
+
+/// A pipe can't seek, only `SeekFrom::Current(0)` is supported, to query the number of bytes read.
+/// Any other seek fails with an error of kind `Unsupported`.
+#[cfg(feature = "tokio")]
+impl tokio::io::AsyncSeek for PipeReader {
+    fn start_seek(self: Pin<&mut Self>, position: io::SeekFrom) -> io::Result<()> {
+        match position {
+            io::SeekFrom::Current(0) => Ok(()),
+            _ => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "{}: {}: A pipe can't seek",
+                    env!("CARGO_PKG_NAME"),
+                    lock(&self.state).describe("PipeReader")
+                ),
+            )),
+        }
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<u64>> {
+        let state = lock(&self.state);
+
+        Poll::Ready(Ok(state.reader(self.id).bytes_read))
+    }
+}
+
+#[cfg(feature = "futures")]
+impl futures::io::AsyncBufRead for PipeReader {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<&[u8]>> {
+        self.poll_fill_buf(cx)
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        self.consume(amt)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl From<PipeReader> for Box<dyn tokio::io::AsyncRead + Unpin + Send> {
+    fn from(reader: PipeReader) -> Self {
+        Box::new(reader)
+    }
+}
+
+#[cfg(feature = "futures")]
+impl From<PipeReader> for Box<dyn futures::io::AsyncRead + Unpin + Send> {
+    fn from(reader: PipeReader) -> Self {
+        Box::new(reader)
     }
 }
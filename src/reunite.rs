@@ -0,0 +1,146 @@
+use crate::{PipeReader, PipeWriter};
+use std::error::Error;
+use std::fmt;
+#[cfg(any(feature = "tokio", feature = "futures"))]
+use std::io;
+#[cfg(any(feature = "tokio", feature = "futures"))]
+use std::pin::Pin;
+use std::sync::Arc;
+#[cfg(any(feature = "tokio", feature = "futures"))]
+use std::task::{Context, Poll};
+
+/// Both halves of a single pipe, recombined by
+/// [`PipeWriter::reunite`](struct.PipeWriter.html#method.reunite).
+///
+/// Bytes written to it are read back from it. Implements both `AsyncRead` and `AsyncWrite` for the
+/// enabled features.
+#[derive(Debug)]
+pub struct Pipe {
+    writer: PipeWriter,
+    reader: PipeReader,
+}
+
+/// Error returned by [`PipeWriter::reunite`](struct.PipeWriter.html#method.reunite) when the two
+/// halves don't belong to the same pipe. It hands both halves back.
+pub struct ReuniteError(pub PipeWriter, pub PipeReader);
+
+impl PipeWriter {
+    /// Recombines the writer with `reader` into a single [`Pipe`](struct.Pipe.html).
+    ///
+    /// Fails if the two halves weren't created by the same [`pipe`](fn.pipe.html) call.
+    pub fn reunite(self, reader: PipeReader) -> Result<Pipe, ReuniteError> {
+        if Arc::ptr_eq(&self.state, &reader.state) {
+            Ok(Pipe {
+                writer: self,
+                reader,
+            })
+        } else {
+            Err(ReuniteError(self, reader))
+        }
+    }
+}
+
+impl Pipe {
+    /// Splits the pipe into its two halves again.
+    pub fn into_split(self) -> (PipeWriter, PipeReader) {
+        (self.writer, self.reader)
+    }
+}
+
+impl fmt::Debug for ReuniteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ReuniteError(..)")
+    }
+}
+
+impl fmt::Display for ReuniteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: tried to reunite halves that are not from the same pipe",
+            env!("CARGO_PKG_NAME")
+        )
+    }
+}
+
+impl Error for ReuniteError {}
+
+#[cfg(feature = "tokio")]
+impl tokio::io::AsyncRead for Pipe {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut tokio::io::ReadBuf,
+    ) -> Poll<io::Result<()>> {
+        tokio::io::AsyncRead::poll_read(Pin::new(&mut self.reader), cx, buf)
+    }
+}
+
+#[cfg(feature = "futures")]
+impl futures::io::AsyncRead for Pipe {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.reader).poll_read_impl(cx, buf)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl tokio::io::AsyncWrite for Pipe {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.writer).poll_write_impl(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        bufs: &[io::IoSlice],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.writer).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        true
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.writer).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.writer).poll_shutdown(cx)
+    }
+}
+
+#[cfg(feature = "futures")]
+impl futures::io::AsyncWrite for Pipe {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.writer).poll_write_impl(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        bufs: &[io::IoSlice],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.writer).poll_write_vectored(cx, bufs)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.writer).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.writer).poll_shutdown(cx)
+    }
+}
@@ -1,10 +1,571 @@
+use crate::buffer::Buffer;
+use crate::builder::CloseStats;
+use crate::error::ClosedError;
+use crate::rate::Rate;
+use crate::reader::EofReason;
+use crate::stats::PipeStats;
+#[cfg(feature = "parking_lot")]
+pub(crate) use parking_lot::{Mutex, MutexGuard};
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+#[cfg(not(feature = "parking_lot"))]
+use std::sync::TryLockError;
+#[cfg(not(feature = "parking_lot"))]
+pub(crate) use std::sync::{Mutex, MutexGuard};
 use std::task::Waker;
 
 pub const BUFFER_SIZE: usize = 1024;
 
+/// Locks the state shared by both halves, ignoring a poisoned lock.
+///
+/// A panic while the lock is held can't leave the state half updated in a way that matters: the
+/// buffer and the reader positions are only changed together by `Buffer` and `State` methods that
+/// don't panic, and a lost waker only delays a task until the next wake up. So the pipe keeps
+/// working instead of failing every call once the lock is poisoned.
+pub(crate) fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    #[cfg(not(feature = "parking_lot"))]
+    {
+        state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+    #[cfg(feature = "parking_lot")]
+    {
+        state.lock()
+    }
+}
+
+/// Locks the state shared by both halves if it is not locked already, ignoring a poisoned lock.
+pub(crate) fn try_lock(state: &Mutex<State>) -> Option<MutexGuard<'_, State>> {
+    #[cfg(not(feature = "parking_lot"))]
+    {
+        match state.try_lock() {
+            Ok(state) => Some(state),
+            Err(TryLockError::Poisoned(err)) => Some(err.into_inner()),
+            Err(TryLockError::WouldBlock) => None,
+        }
+    }
+    #[cfg(feature = "parking_lot")]
+    {
+        state.try_lock()
+    }
+}
+
+/// Stores `waker` in `slot`, unless the stored one already wakes the same task.
+pub(crate) fn register_waker(slot: &mut Option<Waker>, waker: &Waker) {
+    match slot {
+        Some(stored) if stored.will_wake(waker) => {}
+        _ => *slot = Some(waker.clone()),
+    }
+}
+
+/// Unlocks `state`, then runs the `on_close` callback if the pipe was closed while it was locked.
+///
+/// The callback runs without the lock, so it may use the pipe.
+pub(crate) fn unlock(mut state: MutexGuard<'_, State>) {
+    let due = state.on_close_due.take();
+    drop(state);
+    if let Some((OnClose(on_close), stats)) = due {
+        on_close(stats);
+    }
+}
+
+/// The callback set through `PipeBuilder::on_close`.
+pub(crate) struct OnClose(pub(crate) Box<dyn FnOnce(CloseStats) + Send>);
+
+impl fmt::Debug for OnClose {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("OnClose")
+    }
+}
+
 pub(crate) struct State {
-    pub(crate) reader_waker: Option<Waker>,
-    pub(crate) writer_waker: Option<Waker>,
+    pub(crate) readers: HashMap<usize, ReaderState>,
+    pub(crate) writers: HashMap<usize, WriterState>,
+    next_id: usize,
     pub(crate) closed: bool,
-    pub(crate) buffer: Vec<u8>,
+    // Why the pipe was closed, set together with `closed`.
+    pub(crate) eof_reason: Option<EofReason>,
+    // Whether a reader closed the pipe, so it may never read the bytes left.
+    pub(crate) closed_by_reader: bool,
+    // The kind of error writes fail with once the pipe is closed.
+    pub(crate) close_kind: io::ErrorKind,
+    pub(crate) buffer: Buffer,
+    pub(crate) capacity: usize,
+    // Whether the buffered bytes stay readable once the last writer is dropped.
+    pub(crate) flush_on_drop: bool,
+    // Writes only wake the readers once this many bytes are buffered, see `PipeBuilder::coalesce`.
+    pub(crate) coalesce: usize,
+    // Writers wait once this many bytes are buffered, and are only woken again by the readers
+    // once fewer than `low_watermark` are left, see `PipeBuilder::watermarks`.
+    pub(crate) high_watermark: usize,
+    pub(crate) low_watermark: usize,
+    // The number of bytes written by the writers, and of those read by every reader, so dropped
+    // from the buffer. Neither counts preloaded or discarded bytes.
+    pub(crate) bytes_written: u64,
+    pub(crate) bytes_read: u64,
+    // Throughput estimates of the writes and of the reads, if enabled through
+    // `PipeBuilder::with_rate_tracking`.
+    pub(crate) write_rate: Option<Rate>,
+    pub(crate) read_rate: Option<Rate>,
+    // Shared by the two pipes of a `duplex`, to check the halves given to `DuplexPipe::unsplit`.
+    pub(crate) duplex_id: Option<usize>,
+    // The total number of bytes the pipe will carry, if known, see `PipeBuilder::content_length`.
+    pub(crate) content_length: Option<u64>,
+    // How many polls in a row each half may complete before yielding, see
+    // `PipeBuilder::poll_budget`.
+    pub(crate) poll_budget: Option<usize>,
+    // Cancelled once the pipe is closed, see `PipeBuilder::with_cancellation`.
+    #[cfg(feature = "tokio-util")]
+    pub(crate) cancellation: Option<tokio_util::sync::CancellationToken>,
+    // Taken out once the pipe is closed, so it runs only once, and run by `unlock` once the lock
+    // is released.
+    pub(crate) on_close: Option<OnClose>,
+    pub(crate) on_close_due: Option<(OnClose, CloseStats)>,
+    // A label set through `PipeBuilder::name` to tell pipes apart in diagnostics.
+    pub(crate) name: Option<String>,
+    #[cfg(feature = "tracing")]
+    pub(crate) span: tracing::Span,
+}
+
+/// Where a single reader is in the shared buffer.
+pub(crate) struct ReaderState {
+    pub(crate) pos: usize,
+    // Bytes given back through `PipeReader::unread`, read before the ones at `pos`.
+    pub(crate) pushback: Vec<u8>,
+    // The number of bytes read through this reader since it was created.
+    pub(crate) bytes_read: u64,
+    pub(crate) waker: Option<Waker>,
+    // The number of reads completed in a row, counted against the poll budget.
+    pub(crate) streak: usize,
+}
+
+/// Where a reader is in the stream. It changes with every byte the reader consumes, pushes back or
+/// has discarded, so bytes copied out at one cursor are still unread whenever it is the same.
+#[cfg(any(feature = "tokio", feature = "futures"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ReadCursor {
+    bytes_read: u64,
+    pos: usize,
+    pushed_back: usize,
+}
+
+/// A single writer, waiting for free capacity if it has a waker registered.
+pub(crate) struct WriterState {
+    pub(crate) waker: Option<Waker>,
+    // Registered by `PipeWriter::closed`, separately from a pending write on the same writer.
+    pub(crate) closed_waker: Option<Waker>,
+    // Registered by `PipeWriter::flushed`.
+    pub(crate) flushed_waker: Option<Waker>,
+    // The number of writes completed in a row, counted against the poll budget.
+    pub(crate) streak: usize,
+    // Whether the writer was shut down. Unlike `closed` a `reset` keeps it, its writing is done
+    // for good.
+    pub(crate) shutdown: bool,
+}
+
+impl State {
+    /// Creates the state of a pipe holding up to `capacity` bytes, its buffer grows as needed.
+    pub(crate) fn new(capacity: usize) -> State {
+        State {
+            readers: HashMap::new(),
+            writers: HashMap::new(),
+            next_id: 0,
+            closed: false,
+            eof_reason: None,
+            closed_by_reader: false,
+            close_kind: io::ErrorKind::BrokenPipe,
+            buffer: Buffer::with_capacity(capacity.min(BUFFER_SIZE), capacity),
+            capacity,
+            flush_on_drop: true,
+            coalesce: 0,
+            high_watermark: usize::MAX,
+            low_watermark: usize::MAX,
+            bytes_written: 0,
+            bytes_read: 0,
+            write_rate: None,
+            read_rate: None,
+            duplex_id: None,
+            content_length: None,
+            poll_budget: None,
+            on_close: None,
+            on_close_due: None,
+            #[cfg(feature = "tokio-util")]
+            cancellation: None,
+            name: None,
+            #[cfg(feature = "tracing")]
+            span: tracing::trace_span!("pipe"),
+        }
+    }
+
+    pub(crate) fn set_name(&mut self, name: String) {
+        #[cfg(feature = "tracing")]
+        {
+            self.span = tracing::trace_span!("pipe", name = name.as_str());
+        }
+        self.name = Some(name);
+    }
+
+    /// Names `half` in error messages, adding the name of the pipe if it has one.
+    pub(crate) fn describe(&self, half: &str) -> String {
+        match &self.name {
+            Some(name) => format!("{}({})", half, name),
+            None => half.to_string(),
+        }
+    }
+
+    /// Whether writes of the writer `id` fail, because the pipe is closed or it was shut down.
+    pub(crate) fn write_closed(&self, id: usize) -> bool {
+        self.closed || self.writer(id).shutdown
+    }
+
+    /// The error of a write of the writer `id` into the closed pipe, naming the pipe in the message
+    /// if it has a name.
+    ///
+    /// After a shutdown it is always `BrokenPipe`, whatever kind a reader closed the pipe with.
+    pub(crate) fn closed_error(&self, id: usize) -> io::Error {
+        let kind = if self.writer(id).shutdown {
+            io::ErrorKind::BrokenPipe
+        } else {
+            self.close_kind
+        };
+        ClosedError::new(kind, self.name.clone()).into()
+    }
+
+    /// Registers a reader starting at the front of the buffer and returns its id.
+    pub(crate) fn add_reader(&mut self) -> usize {
+        self.add_reader_at(self.buffer.start())
+    }
+
+    /// Registers a reader starting at the absolute position `pos` and returns its id.
+    pub(crate) fn add_reader_at(&mut self, pos: usize) -> usize {
+        let id = self.next_id();
+        self.readers.insert(
+            id,
+            ReaderState {
+                pos,
+                pushback: Vec::new(),
+                bytes_read: 0,
+                waker: None,
+                streak: 0,
+            },
+        );
+        id
+    }
+
+    /// Unregisters the reader `id`, the pipe is closed once the last reader is gone.
+    pub(crate) fn remove_reader(&mut self, id: usize) {
+        self.readers.remove(&id);
+        if self.readers.is_empty() {
+            self.mark_closed(EofReason::Aborted);
+            self.trim();
+        } else {
+            // The bytes the other readers read are only dropped now, behind the slowest reader.
+            self.bytes_read += self.trim() as u64;
+        }
+    }
+
+    /// Registers a writer and returns its id.
+    pub(crate) fn add_writer(&mut self) -> usize {
+        let id = self.next_id();
+        self.writers.insert(
+            id,
+            WriterState {
+                waker: None,
+                closed_waker: None,
+                flushed_waker: None,
+                streak: 0,
+                shutdown: false,
+            },
+        );
+        id
+    }
+
+    /// Unregisters the writer `id`, the pipe is closed once the last writer is gone.
+    pub(crate) fn remove_writer(&mut self, id: usize) {
+        self.writers.remove(&id);
+        self.close_if_writers_done();
+    }
+
+    /// Shuts down the writer `id`, the pipe is closed once every writer is shut down or gone.
+    #[cfg(any(feature = "tokio", feature = "futures"))]
+    pub(crate) fn shutdown_writer(&mut self, id: usize) {
+        self.writer_mut(id).shutdown = true;
+        self.close_if_writers_done();
+    }
+
+    fn close_if_writers_done(&mut self) {
+        if self.writers.values().all(|writer| writer.shutdown) {
+            self.mark_closed(EofReason::WriterFinished);
+            if !self.flush_on_drop {
+                self.discard();
+            }
+        }
+    }
+
+    pub(crate) fn reader(&self, id: usize) -> &ReaderState {
+        self.readers
+            .get(&id)
+            .expect("reader is registered while it is alive")
+    }
+
+    pub(crate) fn reader_mut(&mut self, id: usize) -> &mut ReaderState {
+        self.readers
+            .get_mut(&id)
+            .expect("reader is registered while it is alive")
+    }
+
+    /// The number of bytes the reader `id` has not consumed yet.
+    pub(crate) fn unread_len(&self, id: usize) -> usize {
+        let reader = self.reader(id);
+        reader.pushback.len() + self.buffer.end() - reader.pos
+    }
+
+    #[cfg(any(feature = "tokio", feature = "futures"))]
+    pub(crate) fn read_cursor(&self, id: usize) -> ReadCursor {
+        let reader = self.reader(id);
+        ReadCursor {
+            bytes_read: reader.bytes_read,
+            pos: reader.pos,
+            pushed_back: reader.pushback.len(),
+        }
+    }
+
+    /// Iterates over the bytes the reader `id` has not consumed yet, in reading order.
+    pub(crate) fn unread_slices(&self, id: usize) -> impl Iterator<Item = &[u8]> {
+        let reader = self.reader(id);
+        std::iter::once(&reader.pushback[..])
+            .filter(|slice| !slice.is_empty())
+            .chain(self.buffer.slices_from(reader.pos))
+    }
+
+    /// Copies the unread bytes of the reader `id` into `dst`, after skipping the first `skip` of
+    /// them, without consuming them.
+    pub(crate) fn peek_unread(&self, id: usize, mut skip: usize, dst: &mut [u8]) -> usize {
+        let mut copied = 0;
+        for slice in self.unread_slices(id) {
+            if skip >= slice.len() {
+                skip -= slice.len();
+                continue;
+            }
+            let slice = &slice[skip..];
+            skip = 0;
+            let len = slice.len().min(dst.len() - copied);
+            dst[copied..copied + len].copy_from_slice(&slice[..len]);
+            copied += len;
+            if copied == dst.len() {
+                break;
+            }
+        }
+        copied
+    }
+
+    /// The next contiguous run of unread bytes of the reader `id`, empty if there are none.
+    ///
+    /// A chunk queued by `PipeWriter::write_bytes` is handed out as it is, other bytes are copied.
+    #[cfg(feature = "bytes")]
+    pub(crate) fn unread_bytes(&self, id: usize) -> bytes::Bytes {
+        let reader = self.reader(id);
+        if reader.pushback.is_empty() {
+            if let Some(chunk) = self.buffer.chunk_at(reader.pos) {
+                return chunk;
+            }
+        }
+        self.unread_slices(id)
+            .next()
+            .map(bytes::Bytes::copy_from_slice)
+            .unwrap_or_default()
+    }
+
+    /// Marks `amt` more bytes as consumed by the reader `id`, the pushed back ones first.
+    ///
+    /// Never advances past the unread bytes, even if asked to.
+    pub(crate) fn advance_reader(&mut self, id: usize, amt: usize) {
+        let amt = amt.min(self.unread_len(id));
+        let reader = self.reader_mut(id);
+        let pushed_back = amt.min(reader.pushback.len());
+        reader.pushback.drain(..pushed_back);
+        reader.pos += amt - pushed_back;
+        reader.bytes_read += amt as u64;
+        if let Some(rate) = &mut self.read_rate {
+            rate.record(amt);
+        }
+        self.bytes_read += self.trim() as u64;
+    }
+
+    /// Accounts for `amt` bytes just added to the buffer by a writer.
+    pub(crate) fn record_write(&mut self, amt: usize) {
+        self.bytes_written += amt as u64;
+        if let Some(rate) = &mut self.write_rate {
+            rate.record(amt);
+        }
+    }
+
+    pub(crate) fn writer(&self, id: usize) -> &WriterState {
+        self.writers
+            .get(&id)
+            .expect("writer is registered while it is alive")
+    }
+
+    pub(crate) fn writer_mut(&mut self, id: usize) -> &mut WriterState {
+        self.writers
+            .get_mut(&id)
+            .expect("writer is registered while it is alive")
+    }
+
+    pub(crate) fn wake_writers(&mut self) {
+        for writer in self.writers.values_mut() {
+            if let Some(waker) = writer.waker.take() {
+                waker.wake();
+            }
+            if let Some(waker) = writer.closed_waker.take() {
+                waker.wake();
+            }
+            if let Some(waker) = writer.flushed_waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    pub(crate) fn stats(&self) -> PipeStats {
+        PipeStats {
+            bytes_written: self.bytes_written,
+            bytes_read: self.bytes_read,
+            buffered: self.buffer.len(),
+            closed: self.closed,
+            reader_count: self.readers.len(),
+            writer_count: self.writers.len(),
+        }
+    }
+
+    /// The number of buffered bytes at which writers wait for the readers.
+    pub(crate) fn write_limit(&self) -> usize {
+        self.capacity.min(self.high_watermark)
+    }
+
+    /// Whether a write should wake the readers, it always does once the buffer is full.
+    pub(crate) fn write_wakes_readers(&self) -> bool {
+        self.buffer.len() >= self.coalesce.min(self.write_limit())
+    }
+
+    /// Whether a read should wake the writers, it always does once the buffer is drained so that
+    /// flushes complete.
+    pub(crate) fn read_wakes_writers(&self) -> bool {
+        self.closed || self.buffer.is_empty() || self.buffer.len() < self.low_watermark
+    }
+
+    /// Counts one more poll completed in a row by a half, returns whether the half has used up its
+    /// budget and should yield instead. The streak starts over once it does.
+    pub(crate) fn spend_budget(budget: Option<usize>, streak: &mut usize) -> bool {
+        match budget {
+            Some(budget) if *streak >= budget => {
+                *streak = 0;
+                true
+            }
+            _ => {
+                *streak += 1;
+                false
+            }
+        }
+    }
+
+    pub(crate) fn wake_readers(&mut self) {
+        for reader in self.readers.values_mut() {
+            if let Some(waker) = reader.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Closes the pipe, without waking anyone, and cancels the token tied to it if there is one.
+    ///
+    /// The `reason` is only recorded if the pipe was still open, the first close wins.
+    pub(crate) fn mark_closed(&mut self, reason: EofReason) {
+        if !self.closed {
+            self.eof_reason = Some(reason);
+            if let Some(on_close) = self.on_close.take() {
+                let stats = CloseStats {
+                    bytes_written: self.bytes_written,
+                    bytes_read: self.bytes_read,
+                    reason,
+                };
+                self.on_close_due = Some((on_close, stats));
+            }
+        }
+        self.closed = true;
+        #[cfg(feature = "tokio-util")]
+        if let Some(token) = &self.cancellation {
+            token.cancel();
+        }
+    }
+
+    /// Reopens a closed pipe so both halves can be used again.
+    pub(crate) fn reset(&mut self) -> Result<(), &'static str> {
+        if self.readers.is_empty() || self.writers.is_empty() {
+            return Err("one half of the pipe is gone");
+        }
+        if self.writers.values().all(|writer| writer.shutdown) {
+            return Err("every writer is shut down");
+        }
+        let pushed_back = self
+            .readers
+            .values()
+            .any(|reader| !reader.pushback.is_empty());
+        if !self.buffer.is_empty() || pushed_back {
+            return Err("there is unread data");
+        }
+        let has_waker = self.readers.values().any(|reader| reader.waker.is_some())
+            || self.writers.values().any(|writer| writer.waker.is_some());
+        if has_waker {
+            return Err("an operation is pending");
+        }
+        #[cfg(feature = "tokio-util")]
+        if self.cancellation.is_some() {
+            return Err("the pipe is tied to a cancellation token");
+        }
+
+        self.closed = false;
+        self.eof_reason = None;
+        self.closed_by_reader = false;
+        self.close_kind = io::ErrorKind::BrokenPipe;
+        Ok(())
+    }
+
+    fn next_id(&mut self) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    /// Drops every buffered byte, as if all readers had consumed them.
+    pub(crate) fn discard(&mut self) {
+        let end = self.buffer.end();
+        for reader in self.readers.values_mut() {
+            reader.pos = end;
+            reader.pushback.clear();
+        }
+        self.trim();
+    }
+
+    // Drops the bytes every reader has consumed and returns how many there were.
+    fn trim(&mut self) -> usize {
+        let pos = self
+            .readers
+            .values()
+            .map(|reader| reader.pos)
+            .min()
+            .unwrap_or_else(|| self.buffer.end());
+        let consumed = pos - self.buffer.start();
+        self.buffer.consume(consumed);
+        consumed
+    }
+}
+
+impl Drop for State {
+    // A pipe closed by the last half going away is unlocked by that half, this only catches a
+    // callback left behind by a close that didn't go through `unlock`.
+    fn drop(&mut self) {
+        if let Some((OnClose(on_close), stats)) = self.on_close_due.take() {
+            on_close(stats);
+        }
+    }
 }
@@ -0,0 +1,25 @@
+/// A snapshot of the counters of a pipe, returned by
+/// [`PipeWriter::stats`](struct.PipeWriter.html#method.stats) and
+/// [`PipeReader::stats`](struct.PipeReader.html#method.stats).
+///
+/// All of them are taken together under the lock of the pipe, so they are consistent with each
+/// other. Bytes preloaded through `PipeBuilder::initial` are not counted as written, only as
+/// read once they are. Bytes discarded by `PipeWriter::close_discarding` are not counted as read,
+/// nor are bytes pushed back through `PipeReader::unread` when they are read again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PipeStats {
+    /// The number of bytes written into the pipe.
+    pub bytes_written: u64,
+    /// The number of bytes read by every reader, each byte counted once even if several readers of
+    /// a [`broadcast`](fn.broadcast.html) read it. `PipeReader::bytes_read` counts the bytes of a
+    /// single reader.
+    pub bytes_read: u64,
+    /// The number of bytes still buffered for the slowest reader.
+    pub buffered: usize,
+    /// Whether the pipe is closed.
+    pub closed: bool,
+    /// The number of readers alive.
+    pub reader_count: usize,
+    /// The number of writers alive.
+    pub writer_count: usize,
+}
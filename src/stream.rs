@@ -0,0 +1,45 @@
+use crate::PipeReader;
+use bytes::Bytes;
+use futures::stream::Stream;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// A [`Stream`][futures-stream] of the chunks read from a [`PipeReader`](struct.PipeReader.html).
+///
+/// Created by [`PipeReader::into_stream`](struct.PipeReader.html#method.into_stream). Each item
+/// is a chunk returned by [`PipeReader::read_bytes`](struct.PipeReader.html#method.read_bytes), so
+/// chunks written with `PipeWriter::write_bytes` come out without being copied. The stream ends at
+/// EOF.
+///
+/// [futures-stream]: https://docs.rs/futures/0.3.5/futures/stream/trait.Stream.html
+#[derive(Debug)]
+pub struct PipeStream {
+    reader: PipeReader,
+}
+
+impl PipeReader {
+    /// Turns the reader into a [`Stream`][futures-stream] of chunks.
+    ///
+    /// [futures-stream]: https://docs.rs/futures/0.3.5/futures/stream/trait.Stream.html
+    pub fn into_stream(self) -> PipeStream {
+        PipeStream { reader: self }
+    }
+}
+
+impl PipeStream {
+    /// Returns the reader back, bytes not yielded yet are still buffered in the pipe.
+    pub fn into_inner(self) -> PipeReader {
+        self.reader
+    }
+}
+
+impl Stream for PipeStream {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.get_mut().reader)
+            .poll_read_bytes(cx)
+            .map(Result::transpose)
+    }
+}
@@ -0,0 +1,75 @@
+use crate::PipeReader;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// A [`PipeReader`](struct.PipeReader.html) reading at most a given number of bytes.
+///
+/// Created by [`PipeReader::take`](struct.PipeReader.html#method.take). Reads hit EOF once the
+/// limit is reached, the bytes after it stay buffered in the pipe and can still be read through
+/// [`into_inner`](#method.into_inner).
+#[derive(Debug)]
+pub struct TakeReader {
+    inner: PipeReader,
+    limit: u64,
+}
+
+impl PipeReader {
+    /// Limits the reader to the next `limit` bytes.
+    pub fn take(self, limit: u64) -> TakeReader {
+        TakeReader { inner: self, limit }
+    }
+}
+
+impl TakeReader {
+    /// Returns the number of bytes that can still be read before EOF.
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    /// Returns the reader back, it reads the bytes after the limit.
+    pub fn into_inner(self) -> PipeReader {
+        self.inner
+    }
+
+    #[cfg(feature = "futures")]
+    fn poll_read_limited(&mut self, cx: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        if self.limit == 0 {
+            return Poll::Ready(Ok(0));
+        }
+
+        let len = (buf.len() as u64).min(self.limit) as usize;
+        match Pin::new(&mut self.inner).poll_read_impl(cx, &mut buf[..len]) {
+            Poll::Ready(Ok(n)) => {
+                self.limit -= n as u64;
+                Poll::Ready(Ok(n))
+            }
+            other => other,
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl tokio::io::AsyncRead for TakeReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut tokio::io::ReadBuf,
+    ) -> Poll<io::Result<()>> {
+        let len = (buf.remaining() as u64).min(self.limit) as usize;
+        let n = std::task::ready!(Pin::new(&mut self.inner).poll_read_buf(cx, buf, len))?;
+        self.limit -= n as u64;
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature = "futures")]
+impl futures::io::AsyncRead for TakeReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_read_limited(cx, buf)
+    }
+}
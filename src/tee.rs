@@ -0,0 +1,132 @@
+use crate::PipeReader;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// A [`PipeReader`](struct.PipeReader.html) also writing every byte it reads into a sink.
+///
+/// Created by [`PipeReader::tee`](struct.PipeReader.html#method.tee). Bytes are only returned by
+/// a read once the sink accepted them, so a slow sink slows the reader down. Implements
+/// `AsyncRead` for the enabled features, if the sink implements the matching `AsyncWrite`.
+#[derive(Debug)]
+pub struct TeeReader<W> {
+    inner: PipeReader,
+    sink: W,
+    // Bytes read from the pipe, the first `mirrored` of them are written into the sink and the
+    // first `returned` of those are handed to the caller.
+    pending: Vec<u8>,
+    mirrored: usize,
+    returned: usize,
+}
+
+impl PipeReader {
+    /// Mirrors the bytes read into `sink`.
+    pub fn tee<W>(self, sink: W) -> TeeReader<W> {
+        TeeReader {
+            inner: self,
+            sink,
+            pending: Vec::new(),
+            mirrored: 0,
+            returned: 0,
+        }
+    }
+}
+
+impl<W> TeeReader<W> {
+    /// Returns the reader and the sink back. Bytes read but not returned by a read yet are lost.
+    pub fn into_inner(self) -> (PipeReader, W) {
+        (self.inner, self.sink)
+    }
+
+    // Reads up to `len` bytes from the pipe and mirrors them into the sink, then returns the
+    // mirrored bytes not handed to the caller yet. `consume_tee` marks them as returned.
+    fn poll_fill_tee<F>(
+        &mut self,
+        cx: &mut Context,
+        len: usize,
+        mut poll_write: F,
+    ) -> Poll<io::Result<&[u8]>>
+    where
+        F: FnMut(&mut W, &mut Context, &[u8]) -> Poll<io::Result<usize>>,
+    {
+        if len == 0 {
+            return Poll::Ready(Ok(&[]));
+        }
+
+        if self.pending.is_empty() {
+            self.pending.resize(len, 0);
+            match Pin::new(&mut self.inner).poll_read_impl(cx, &mut self.pending) {
+                Poll::Ready(Ok(n)) => self.pending.truncate(n),
+                Poll::Ready(Err(err)) => {
+                    self.pending.clear();
+                    return Poll::Ready(Err(err));
+                }
+                Poll::Pending => {
+                    self.pending.clear();
+                    return Poll::Pending;
+                }
+            }
+        }
+
+        while self.mirrored < self.pending.len() {
+            match poll_write(&mut self.sink, cx, &self.pending[self.mirrored..]) {
+                Poll::Ready(Ok(0)) => {
+                    return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+                }
+                Poll::Ready(Ok(n)) => self.mirrored += n,
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        Poll::Ready(Ok(&self.pending[self.returned..]))
+    }
+
+    fn consume_tee(&mut self, amt: usize) {
+        self.returned += amt;
+        if self.returned == self.pending.len() {
+            self.pending.clear();
+            self.mirrored = 0;
+            self.returned = 0;
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<W: tokio::io::AsyncWrite + Unpin> tokio::io::AsyncRead for TeeReader<W> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut tokio::io::ReadBuf,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let data = std::task::ready!(this.poll_fill_tee(cx, buf.remaining(), |sink, cx, data| {
+            Pin::new(sink).poll_write(cx, data)
+        }))?;
+
+        // `put_slice` fills the caller's buffer without zeroing its uninitialized part first.
+        let len = data.len().min(buf.remaining());
+        buf.put_slice(&data[..len]);
+        this.consume_tee(len);
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<W: futures::io::AsyncWrite + Unpin> futures::io::AsyncRead for TeeReader<W> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let data = std::task::ready!(this.poll_fill_tee(cx, buf.len(), |sink, cx, data| {
+            Pin::new(sink).poll_write(cx, data)
+        }))?;
+
+        let len = data.len().min(buf.len());
+        buf[..len].copy_from_slice(&data[..len]);
+        this.consume_tee(len);
+        Poll::Ready(Ok(len))
+    }
+}
@@ -0,0 +1,151 @@
+use crate::time::{Instant, Sleep, Timer};
+use crate::PipeWriter;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// A [`PipeWriter`](struct.PipeWriter.html) accepting at most a given number of bytes per second.
+///
+/// Created by [`PipeWriter::throttle`](struct.PipeWriter.html#method.throttle). Implements
+/// `AsyncWrite` for the enabled features, a write waits once the budget is used up. Up to a second
+/// worth of budget builds up while nothing is written.
+#[derive(Debug)]
+pub struct ThrottledWriter {
+    inner: PipeWriter,
+    bytes_per_sec: u64,
+    // The number of bytes that can be written right away, and when it was last topped up.
+    budget: u64,
+    refilled_at: Instant,
+    // Created on the first wait, so the writer can be throttled outside of a runtime.
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl PipeWriter {
+    /// Limits the writer to `bytes_per_sec` bytes per second, for example to simulate a slow link.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes_per_sec` is zero.
+    pub fn throttle(self, bytes_per_sec: u64) -> ThrottledWriter {
+        assert!(
+            bytes_per_sec > 0,
+            "{}: bytes_per_sec must be non-zero",
+            env!("CARGO_PKG_NAME")
+        );
+
+        let now = Instant::now();
+        ThrottledWriter {
+            inner: self,
+            bytes_per_sec,
+            budget: 0,
+            refilled_at: now,
+            sleep: None,
+        }
+    }
+}
+
+impl ThrottledWriter {
+    /// Returns the writer back without the limit.
+    pub fn into_inner(self) -> PipeWriter {
+        self.inner
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.refilled_at).as_nanos();
+        let earned = elapsed * self.bytes_per_sec as u128 / NANOS_PER_SEC;
+        if earned == 0 {
+            return;
+        }
+
+        let budget = self.budget as u128 + earned;
+        if budget >= self.bytes_per_sec as u128 {
+            self.budget = self.bytes_per_sec;
+            self.refilled_at = now;
+        } else {
+            self.budget = budget as u64;
+            // Only count the time it took to earn whole bytes, the rest carries over.
+            self.refilled_at += self.time_for(earned);
+        }
+    }
+
+    fn time_for(&self, bytes: u128) -> Duration {
+        let nanos = bytes * NANOS_PER_SEC / self.bytes_per_sec as u128;
+        Duration::from_nanos(nanos as u64)
+    }
+
+    fn poll_write_throttled(&mut self, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        self.refill(Instant::now());
+        while self.budget == 0 {
+            // Wait until the next byte is earned, rounding up.
+            let deadline = self.refilled_at + self.time_for(1) + Duration::from_nanos(1);
+            let sleep = match &mut self.sleep {
+                Some(sleep) => {
+                    sleep.as_mut().reset_at(deadline);
+                    sleep
+                }
+                None => self
+                    .sleep
+                    .get_or_insert_with(|| Box::pin(Sleep::at(deadline))),
+            };
+            if sleep.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            self.refill(Instant::now());
+        }
+
+        let len = buf.len().min(self.budget as usize);
+        match Pin::new(&mut self.inner).poll_write_impl(cx, &buf[..len]) {
+            Poll::Ready(Ok(n)) => {
+                self.budget -= n as u64;
+                Poll::Ready(Ok(n))
+            }
+            other => other,
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl tokio::io::AsyncWrite for ThrottledWriter {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_write_throttled(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(feature = "futures")]
+impl futures::io::AsyncWrite for ThrottledWriter {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_write_throttled(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,48 @@
+use std::future::Future;
+use std::pin::Pin;
+
+#[cfg(feature = "tokio")]
+pub(crate) use tokio::time::{Instant, Sleep};
+
+#[cfg(not(feature = "tokio"))]
+pub(crate) use futures_timer::Delay as Sleep;
+#[cfg(all(
+    not(feature = "tokio"),
+    not(all(target_arch = "wasm32", target_os = "unknown"))
+))]
+pub(crate) use std::time::Instant;
+#[cfg(all(not(feature = "tokio"), target_arch = "wasm32", target_os = "unknown"))]
+pub(crate) use web_time::Instant;
+
+/// A future completing at a deadline, which can be moved without creating a new timer.
+///
+/// The time based adapters use tokio's timer if feature `tokio` is enabled, otherwise the one of
+/// `futures-timer`, which runs on its own thread and works with any executor.
+pub(crate) trait Timer: Future<Output = ()> + Send {
+    fn at(deadline: Instant) -> Self;
+
+    fn reset_at(self: Pin<&mut Self>, deadline: Instant);
+}
+
+#[cfg(feature = "tokio")]
+impl Timer for Sleep {
+    fn at(deadline: Instant) -> Sleep {
+        tokio::time::sleep_until(deadline)
+    }
+
+    fn reset_at(self: Pin<&mut Self>, deadline: Instant) {
+        self.reset(deadline);
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl Timer for Sleep {
+    fn at(deadline: Instant) -> Sleep {
+        Sleep::new(deadline.saturating_duration_since(Instant::now()))
+    }
+
+    fn reset_at(self: Pin<&mut Self>, deadline: Instant) {
+        let dur = deadline.saturating_duration_since(Instant::now());
+        self.get_mut().reset(dur);
+    }
+}
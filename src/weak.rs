@@ -0,0 +1,34 @@
+use crate::state::{lock, Mutex, State};
+use crate::PipeWriter;
+use std::sync::{Arc, Weak};
+
+/// A [`PipeWriter`](struct.PipeWriter.html) handle that doesn't count as a writer.
+///
+/// Created by [`PipeWriter::downgrade`](struct.PipeWriter.html#method.downgrade). It doesn't keep
+/// the pipe open: once every `PipeWriter` is dropped the reader sees EOF and
+/// [`upgrade`](#method.upgrade) returns `None`, even if weak handles remain.
+#[derive(Clone, Debug)]
+pub struct WeakPipeWriter {
+    state: Weak<Mutex<State>>,
+}
+
+impl PipeWriter {
+    /// Creates a [`WeakPipeWriter`](struct.WeakPipeWriter.html) for this pipe.
+    pub fn downgrade(&self) -> WeakPipeWriter {
+        WeakPipeWriter {
+            state: Arc::downgrade(&self.state),
+        }
+    }
+}
+
+impl WeakPipeWriter {
+    /// Returns a new writer for the pipe, or `None` if every `PipeWriter` has been dropped.
+    pub fn upgrade(&self) -> Option<PipeWriter> {
+        let state = self.state.upgrade()?;
+        if lock(&state).writers.is_empty() {
+            return None;
+        }
+
+        Some(PipeWriter::new(state))
+    }
+}
@@ -1,7 +1,13 @@
-use crate::state::{State, BUFFER_SIZE};
-use std::io;
+use crate::error::PipeError;
+use crate::reader::EofReason;
+use crate::state::{lock, register_waker, try_lock, unlock, Mutex, State};
+use crate::stats::PipeStats;
+#[cfg(feature = "bytes")]
+use bytes::{Buf, Bytes};
+use std::fmt;
+use std::io::{self, IoSlice};
 use std::pin::Pin;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 use std::task::{Context, Poll};
 
 /// The write half of the pipe
@@ -10,138 +16,506 @@ use std::task::{Context, Poll};
 /// default). Implements [`futures::io::AsyncWrite`][futures-async-write] when feature `futures` is
 /// enabled.
 ///
+/// Cloning a writer gives another handle writing into the same pipe, the reader only sees EOF once
+/// every clone is shut down or dropped, or the pipe is closed. Bytes accepted by a single `write` call stay
+/// contiguous, but there is no ordering guarantee between writes made through different clones.
+///
+/// Flushing waits until every byte written so far is read, by every reader of a
+/// [`broadcast`](fn.broadcast.html) pipe. Shutting down flushes first, then stops this writer:
+/// its further writes fail, and the pipe is closed once every clone is shut down or dropped.
+///
+/// Writes are cancel safe: a write that returns `Pending` hasn't accepted any bytes, so dropping
+/// its future leaves nothing half written.
+///
 /// [futures-async-write]: https://docs.rs/futures/0.3.5/futures/io/trait.AsyncWrite.html
-/// [tokio-async-write]: https://docs.rs/tokio/0.2.16/tokio/io/trait.AsyncWrite.html
+/// [tokio-async-write]: https://docs.rs/tokio/1/tokio/io/trait.AsyncWrite.html
 pub struct PipeWriter {
     pub(crate) state: Arc<Mutex<State>>,
+    pub(crate) id: usize,
 }
 
 impl PipeWriter {
+    /// Registers a new writer on `state`.
+    pub(crate) fn new(state: Arc<Mutex<State>>) -> PipeWriter {
+        let id = lock(&state).add_writer();
+
+        PipeWriter { state, id }
+    }
+
+    /// Returns an id of the pipe, shared by all of its readers and writers.
+    ///
+    /// Halves of different pipes have different ids as long as both pipes are alive, an id may
+    /// be reused once every half of its pipe is dropped. Writers compare equal if their ids do.
+    pub fn pipe_id(&self) -> usize {
+        Arc::as_ptr(&self.state) as usize
+    }
+
+    /// Boxes the writer as a trait object, to store writers of different types together.
+    ///
+    /// The trait object is `dyn tokio::io::AsyncWrite + Unpin + Send` with feature `tokio`, and
+    /// `dyn futures::io::AsyncWrite + Unpin + Send` with feature `futures`. With both features
+    /// enabled, the type the result is assigned to picks the one.
+    #[cfg(any(feature = "tokio", feature = "futures"))]
+    pub fn into_dyn<T: ?Sized>(self) -> Box<T>
+    where
+        Box<T>: From<PipeWriter>,
+    {
+        self.into()
+    }
+
     /// Closes the pipe, any further read will return EOF and any further write will raise an error.
-    pub fn close(&self) -> io::Result<()> {
-        match self.state.lock() {
-            Ok(mut state) => {
-                state.closed = true;
-                self.wake_reader_half(&*state);
-                Ok(())
-            }
-            Err(err) => Err(io::Error::new(
-                io::ErrorKind::Other,
-                format!(
-                    "{}: PipeWriter: Failed to lock the channel state: {}",
-                    env!("CARGO_PKG_NAME"),
-                    err
-                ),
-            )),
+    ///
+    /// The readers see [`EofReason::Aborted`](enum.EofReason.html#variant.Aborted), use
+    /// `shutdown` of `AsyncWrite` to signal that writing is done instead.
+    pub fn close(&self) -> Result<(), PipeError> {
+        let mut state = lock(&self.state);
+
+        state.mark_closed(EofReason::Aborted);
+        self.wake_reader_half(&mut state);
+        // Clones waiting for free capacity or for the pipe to close.
+        state.wake_writers();
+        trace!(state, "close");
+        unlock(state);
+        Ok(())
+    }
+
+    /// Closes the pipe like [`close`](#method.close), but also drops the bytes not read yet and
+    /// returns how many there were.
+    ///
+    /// The reader sees EOF right away instead of reading the buffered bytes first.
+    pub fn close_discarding(&self) -> Result<usize, PipeError> {
+        let mut state = lock(&self.state);
+
+        let discarded = state.buffer.len();
+        // Closing first, so the discarded bytes don't count as read.
+        state.mark_closed(EofReason::Aborted);
+        state.discard();
+        self.wake_reader_half(&mut state);
+        state.wake_writers();
+        trace!(state, discarded, "close");
+        unlock(state);
+        Ok(discarded)
+    }
+
+    /// Waits until the pipe is closed, because it was closed explicitly or every reader is gone.
+    pub async fn closed(&self) {
+        std::future::poll_fn(|cx| self.poll_closed(cx)).await
+    }
+
+    fn poll_closed(&self, cx: &mut Context) -> Poll<()> {
+        let mut state = lock(&self.state);
+
+        if state.closed {
+            Poll::Ready(())
+        } else {
+            register_waker(&mut state.writer_mut(self.id).closed_waker, cx.waker());
+            Poll::Pending
+        }
+    }
+
+    /// Waits until every byte written so far is consumed, like a flush that doesn't need `&mut`.
+    ///
+    /// Unlike [`is_flushed`](#method.is_flushed) it doesn't have to be polled in a loop.
+    pub async fn flushed(&self) {
+        std::future::poll_fn(|cx| self.poll_flushed(cx)).await
+    }
+
+    fn poll_flushed(&self, cx: &mut Context) -> Poll<()> {
+        let mut state = lock(&self.state);
+
+        if state.buffer.is_empty() {
+            Poll::Ready(())
+        } else {
+            register_waker(&mut state.writer_mut(self.id).flushed_waker, cx.waker());
+            Poll::Pending
         }
     }
 
+    /// Reopens a closed pipe so the same pair of halves can be used again.
+    ///
+    /// Fails if there is unread data, if a read or write is still pending, if the other half is
+    /// gone, or if every writer is shut down. Writes through a writer that was shut down still fail
+    /// afterwards, its writing is done for good.
+    pub fn reset(&self) -> io::Result<()> {
+        let mut state = lock(&self.state);
+
+        state.reset().map_err(|reason| {
+            io::Error::other(format!(
+                "{}: {}: Failed to reset the channel: {}",
+                env!("CARGO_PKG_NAME"),
+                state.describe("PipeWriter"),
+                reason
+            ))
+        })
+    }
+
+    /// Returns true once the pipe is closed, either explicitly or because every reader or every
+    /// writer is gone.
+    ///
+    /// Never fails, the `Result` is kept from when a poisoned lock was reported as
+    /// [`PipeError::Poisoned`](enum.PipeError.html#variant.Poisoned), so callers keep compiling.
+    pub fn is_closed(&self) -> Result<bool, PipeError> {
+        let state = lock(&self.state);
+
+        Ok(state.closed)
+    }
+
     /// It returns true if the next data chunk is written and consumed by the reader; Otherwise it returns false.
-    pub fn is_flushed(&self) -> io::Result<bool> {
-        let state = match self.state.lock() {
-            Ok(s) => s,
-            Err(err) => {
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    format!(
-                        "{}: PipeWriter: Failed to lock the channel state: {}",
-                        env!("CARGO_PKG_NAME"),
-                        err
-                    ),
-                ));
-            }
-        };
+    ///
+    /// Never fails, like [`is_closed`](#method.is_closed).
+    pub fn is_flushed(&self) -> Result<bool, PipeError> {
+        let state = lock(&self.state);
 
         Ok(state.buffer.is_empty())
     }
 
-    fn wake_reader_half(&self, state: &State) {
-        if let Some(ref waker) = state.reader_waker {
-            waker.clone().wake();
+    /// Returns the total number of bytes written into the pipe, by this writer and all its clones.
+    ///
+    /// The count only grows, it is kept when the pipe is [`reset`](#method.reset).
+    pub fn bytes_written(&self) -> Result<u64, PipeError> {
+        let state = lock(&self.state);
+
+        Ok(state.bytes_written)
+    }
+
+    /// Returns the number of bytes the pipe buffers between the two halves.
+    pub fn capacity(&self) -> usize {
+        lock(&self.state).capacity
+    }
+
+    /// Returns the number of bytes that can be written right away, `0` once the pipe is closed.
+    pub fn available(&self) -> Result<usize, PipeError> {
+        let state = lock(&self.state);
+
+        if state.write_closed(self.id) {
+            return Ok(0);
         }
+        Ok(state.write_limit().saturating_sub(state.buffer.len()))
     }
 
-    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
-        if Arc::strong_count(&self.state) == 1 {
-            return Poll::Ready(Err(io::Error::new(
-                io::ErrorKind::BrokenPipe,
-                format!(
-                    "{}: PipeWriter: The channel is closed",
-                    env!("CARGO_PKG_NAME")
-                ),
-            )));
-        }
-
-        let mut state = match self.state.lock() {
-            Ok(s) => s,
-            Err(err) => {
-                return Poll::Ready(Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    format!(
-                        "{}: PipeWriter: Failed to lock the channel state: {}",
-                        env!("CARGO_PKG_NAME"),
-                        err
-                    ),
-                )))
+    /// Returns the number of bytes per second written into the pipe over the last second, by this
+    /// writer and all its clones.
+    ///
+    /// Returns `None` unless the pipe was built with
+    /// [`with_rate_tracking`](struct.PipeBuilder.html#method.with_rate_tracking).
+    pub fn bytes_per_sec(&self) -> Option<f64> {
+        let mut state = lock(&self.state);
+
+        state.write_rate.as_mut().map(|rate| rate.per_sec())
+    }
+
+    /// Returns the number of live readers of the pipe.
+    pub fn reader_count(&self) -> Result<usize, PipeError> {
+        let state = lock(&self.state);
+
+        Ok(state.readers.len())
+    }
+
+    /// Returns the number of live writers of the pipe, this one and all its clones.
+    pub fn writer_count(&self) -> Result<usize, PipeError> {
+        let state = lock(&self.state);
+
+        Ok(state.writers.len())
+    }
+
+    /// Returns the counters of the pipe, all taken at the same time.
+    ///
+    /// Never fails, like [`is_closed`](#method.is_closed).
+    pub fn stats(&self) -> Result<PipeStats, PipeError> {
+        let state = lock(&self.state);
+
+        Ok(state.stats())
+    }
+
+    /// Writes out every remaining byte of `buf`, advancing it as the bytes are accepted.
+    ///
+    /// Waits for free capacity like a regular `write`. If the future is dropped, `buf` has been
+    /// advanced past the bytes written so far.
+    #[cfg(feature = "bytes")]
+    pub async fn write_all_buf<B: Buf>(&mut self, buf: &mut B) -> io::Result<()> {
+        while buf.has_remaining() {
+            let n = std::future::poll_fn(|cx| {
+                let mut slices = [IoSlice::new(&[]); 16];
+                let count = buf.chunks_vectored(&mut slices);
+                Pin::new(&mut *self).poll_write_vectored(cx, &slices[..count])
+            })
+            .await?;
+            buf.advance(n);
+        }
+        Ok(())
+    }
+
+    /// Writes all of `buf` like `write_all`, but on an error also returns how many bytes the pipe
+    /// accepted before it.
+    ///
+    /// Accepted bytes were buffered for the readers, they may still be dropped unread if the pipe
+    /// is closed by the reader. Returns `buf.len()` on success.
+    pub async fn write_all_counted(&mut self, buf: &[u8]) -> Result<usize, (io::Error, usize)> {
+        let mut written = 0;
+        while written < buf.len() {
+            let rest = &buf[written..];
+            match std::future::poll_fn(|cx| Pin::new(&mut *self).poll_write_impl(cx, rest)).await {
+                Ok(n) => written += n,
+                Err(err) => return Err((err, written)),
             }
-        };
+        }
+        Ok(written)
+    }
+
+    /// Writes every byte of every slice of `bufs`, like `write_all` but without concatenating them
+    /// first.
+    ///
+    /// Waits for free capacity as often as needed, so the slices may add up to more than the
+    /// capacity of the pipe. The slices are advanced in place as they are written, so like for
+    /// `std::io::Write::write_all_vectored` their contents are unspecified once it returns.
+    pub async fn write_all_vectored(&mut self, mut bufs: &mut [IoSlice<'_>]) -> io::Result<()> {
+        // Skips the leading empty slices, a vectored write of only empty ones returns 0.
+        IoSlice::advance_slices(&mut bufs, 0);
+        while !bufs.is_empty() {
+            let n = std::future::poll_fn(|cx| Pin::new(&mut *self).poll_write_vectored(cx, bufs))
+                .await?;
+            IoSlice::advance_slices(&mut bufs, n);
+        }
+        Ok(())
+    }
+
+    /// Hands `data` over to the reader without copying it.
+    ///
+    /// Waits until bytes written with a regular `write` are consumed by the reader, and the pipe
+    /// has free capacity. The whole chunk is queued at once, even if it is larger than the
+    /// capacity of the pipe.
+    #[cfg(feature = "bytes")]
+    pub async fn write_bytes(&mut self, data: Bytes) -> io::Result<()> {
+        let mut data = Some(data);
+        std::future::poll_fn(|cx| self.poll_write_bytes(cx, &mut data)).await
+    }
+
+    #[cfg(feature = "bytes")]
+    fn poll_write_bytes(&self, cx: &mut Context, data: &mut Option<Bytes>) -> Poll<io::Result<()>> {
+        std::task::ready!(self.poll_bytes_ready(cx))?;
+
+        match data.take() {
+            Some(data) => Poll::Ready(self.push_bytes(data)),
+            None => Poll::Ready(Ok(())),
+        }
+    }
+
+    // Waits until a chunk can be queued without copying it.
+    #[cfg(feature = "bytes")]
+    fn poll_bytes_ready(&self, cx: &mut Context) -> Poll<io::Result<()>> {
+        let mut state = lock(&self.state);
+
+        if state.write_closed(self.id) {
+            return Poll::Ready(Err(state.closed_error(self.id)));
+        }
+
+        self.wake_reader_half(&mut state);
+
+        if !state.buffer.can_push_chunk() || state.buffer.len() >= state.write_limit() {
+            register_waker(&mut state.writer_mut(self.id).waker, cx.waker());
+            return Poll::Pending;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    // Queues `data` for the reader. It is copied only if a clone wrote regular bytes since the
+    // writer was ready, to keep them in order.
+    #[cfg(feature = "bytes")]
+    fn push_bytes(&self, data: Bytes) -> io::Result<()> {
+        let mut state = lock(&self.state);
 
-        self.wake_reader_half(&*state);
+        if state.write_closed(self.id) {
+            return Err(state.closed_error(self.id));
+        }
+
+        let len = data.len();
+        if state.buffer.can_push_chunk() {
+            state.buffer.push_chunk(data);
+        } else {
+            state.buffer.extend_from_slice(&data);
+        }
+        state.record_write(len);
+        if state.write_wakes_readers() {
+            self.wake_reader_half(&mut state);
+        }
+        Ok(())
+    }
 
-        let remaining = BUFFER_SIZE - state.buffer.len();
+    /// Writes as many bytes as fit into the pipe right away without waiting.
+    ///
+    /// Returns an error of kind `WouldBlock` if the pipe is full, and of the kind the pipe was
+    /// closed with (`BrokenPipe` by default) if it is closed. Unlike a regular write, no waker is
+    /// registered.
+    pub fn try_write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = lock(&self.state);
+
+        if state.write_closed(self.id) {
+            return Err(state.closed_error(self.id));
+        }
+
+        let remaining = state.write_limit().saturating_sub(state.buffer.len());
         if remaining == 0 {
-            state.writer_waker = Some(cx.waker().clone());
-            Poll::Pending
+            Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                format!(
+                    "{}: {}: The channel is full",
+                    env!("CARGO_PKG_NAME"),
+                    state.describe("PipeWriter")
+                ),
+            ))
         } else {
             let bytes_to_write = remaining.min(buf.len());
             state.buffer.extend_from_slice(&buf[..bytes_to_write]);
-            Poll::Ready(Ok(bytes_to_write))
+            state.record_write(bytes_to_write);
+            if state.write_wakes_readers() {
+                self.wake_reader_half(&mut state);
+            }
+            Ok(bytes_to_write)
         }
     }
 
-    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
-        let mut state = match self.state.lock() {
-            Ok(s) => s,
-            Err(err) => {
-                return Poll::Ready(Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    format!(
-                        "{}: PipeWriter: Failed to lock the channel state: {}",
-                        env!("CARGO_PKG_NAME"),
-                        err
-                    ),
-                )))
+    fn wake_reader_half(&self, state: &mut State) {
+        state.wake_readers();
+    }
+
+    /// Attempts to write `buf`, the poll behind the `AsyncWrite` impls.
+    ///
+    /// For driving the writer from a hand written `Future` without going through one of the
+    /// `AsyncWrite` traits. Returns the number of bytes accepted, or `Pending` after registering
+    /// the waker of `cx` to be woken once there is free capacity.
+    ///
+    /// As much of `buf` as fits into the free capacity is accepted at once, so `write_all` only
+    /// waits for the reader once per full buffer rather than once per call. Any free byte is
+    /// used, so a message larger than the capacity is delivered incrementally as the reader
+    /// makes room, instead of waiting for space the pipe can never have.
+    pub fn poll_write_impl(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_write_vectored(cx, &[IoSlice::new(buf)])
+    }
+
+    pub(crate) fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        bufs: &[IoSlice],
+    ) -> Poll<io::Result<usize>> {
+        // Nothing to hand over, the reader doesn't need to be woken either.
+        if bufs.iter().all(|buf| buf.is_empty()) {
+            return Poll::Ready(Ok(0));
+        }
+
+        let mut state = lock(&self.state);
+
+        // Closing sets the flag and wakes the writers under this same lock, so a writer can't
+        // register its waker after missing the wake up.
+        if state.write_closed(self.id) {
+            trace!(state, poll = "ready", error = "closed", "poll_write");
+            return Poll::Ready(Err(state.closed_error(self.id)));
+        }
+
+        let remaining = state.write_limit().saturating_sub(state.buffer.len());
+        if remaining == 0 {
+            self.wake_reader_half(&mut state);
+            let writer = state.writer_mut(self.id);
+            writer.streak = 0;
+            register_waker(&mut writer.waker, cx.waker());
+            trace!(state, poll = "pending", "poll_write");
+            Poll::Pending
+        } else {
+            let budget = state.poll_budget;
+            if State::spend_budget(budget, &mut state.writer_mut(self.id).streak) {
+                cx.waker().wake_by_ref();
+                trace!(state, poll = "pending", reason = "budget", "poll_write");
+                return Poll::Pending;
             }
-        };
+
+            let mut bytes_written = 0;
+            for buf in bufs {
+                let bytes_to_write = (remaining - bytes_written).min(buf.len());
+                state.buffer.extend_from_slice(&buf[..bytes_to_write]);
+                bytes_written += bytes_to_write;
+                if bytes_written == remaining {
+                    break;
+                }
+            }
+            state.record_write(bytes_written);
+            if state.write_wakes_readers() {
+                self.wake_reader_half(&mut state);
+            }
+            trace!(state, bytes = bytes_written, poll = "ready", "poll_write");
+            Poll::Ready(Ok(bytes_written))
+        }
+    }
+
+    // Pending until the buffer is drained, readers wake the writer whenever they consume bytes.
+    pub(crate) fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        let mut state = lock(&self.state);
+
+        // The bytes of the last reader were dropped with it, so they can't have been delivered. A
+        // reader closing the pipe says it is done too, it isn't expected to read what is left.
+        if state.readers.is_empty() || (state.closed_by_reader && !state.buffer.is_empty()) {
+            return Poll::Ready(Err(state.closed_error(self.id)));
+        }
 
         if state.buffer.is_empty() {
             Poll::Ready(Ok(()))
         } else {
-            state.writer_waker = Some(cx.waker().clone());
-            self.wake_reader_half(&*state);
+            register_waker(&mut state.writer_mut(self.id).waker, cx.waker());
+            self.wake_reader_half(&mut state);
             Poll::Pending
         }
     }
 
-    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
-        match self.close() {
-            Ok(_) => Poll::Ready(Ok(())),
-            Err(err) => Poll::Ready(Err(io::Error::new(
-                io::ErrorKind::Other,
-                format!(
-                    "{}: PipeWriter: Failed to shutdown the channel: {}",
-                    env!("CARGO_PKG_NAME"),
-                    err
-                ),
-            ))),
+    // Waits for the readers to drain the buffer, then shuts this writer down, once shut down it's a
+    // no-op. The pipe is only closed once every writer is shut down or dropped. Further writes
+    // through this writer fail with `BrokenPipe`, even after a `reset`.
+    #[cfg(any(feature = "tokio", feature = "futures"))]
+    pub(crate) fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+    ) -> Poll<io::Result<()>> {
+        {
+            let mut state = lock(&self.state);
+            if state.closed || state.writer(self.id).shutdown {
+                state.shutdown_writer(self.id);
+                trace!(state, poll = "ready", "shutdown");
+                return Poll::Ready(Ok(()));
+            }
+        }
+
+        std::task::ready!(self.as_mut().poll_flush(cx))?;
+        let mut state = lock(&self.state);
+        state.shutdown_writer(self.id);
+        if state.closed {
+            self.wake_reader_half(&mut state);
+            // Clones waiting for free capacity or for the pipe to close.
+            state.wake_writers();
         }
+        trace!(state, poll = "ready", "shutdown");
+        unlock(state);
+        Poll::Ready(Ok(()))
     }
 }
 
 #[cfg(feature = "tokio")]
 impl tokio::io::AsyncWrite for PipeWriter {
     fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
-        self.poll_write(cx, buf)
+        self.poll_write_impl(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        bufs: &[IoSlice],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        true
     }
 
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
@@ -156,7 +530,38 @@ impl tokio::io::AsyncWrite for PipeWriter {
 #[cfg(feature = "futures")]
 impl futures::io::AsyncWrite for PipeWriter {
     fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
-        self.poll_write(cx, buf)
+        self.poll_write_impl(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        bufs: &[IoSlice],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_write_vectored(cx, bufs)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        self.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        self.poll_shutdown(cx)
+    }
+}
+
+/// Queues each chunk for the reader without copying it, like
+/// [`write_bytes`](struct.PipeWriter.html#method.write_bytes) does.
+#[cfg(all(feature = "futures", feature = "bytes"))]
+impl futures::sink::Sink<Bytes> for PipeWriter {
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        self.poll_bytes_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Bytes) -> io::Result<()> {
+        self.push_bytes(item)
     }
 
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
@@ -167,3 +572,61 @@ impl futures::io::AsyncWrite for PipeWriter {
         self.poll_shutdown(cx)
     }
 }
+
+impl Clone for PipeWriter {
+    fn clone(&self) -> PipeWriter {
+        PipeWriter::new(Arc::clone(&self.state))
+    }
+}
+
+impl PartialEq for PipeWriter {
+    /// Whether both handles belong to the same pipe, see [`pipe_id`](#method.pipe_id).
+    fn eq(&self, other: &PipeWriter) -> bool {
+        Arc::ptr_eq(&self.state, &other.state)
+    }
+}
+
+impl Eq for PipeWriter {}
+
+impl fmt::Debug for PipeWriter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut d = f.debug_struct("PipeWriter");
+        let state = match try_lock(&self.state) {
+            Some(state) => state,
+            None => return d.field("state", &format_args!("<locked>")).finish(),
+        };
+
+        if let Some(name) = &state.name {
+            d.field("name", name);
+        }
+        d.field("closed", &state.closed)
+            .field("buffered", &state.buffer.len())
+            .field("waker", &state.writer(self.id).waker.is_some())
+            .finish()
+    }
+}
+
+impl Drop for PipeWriter {
+    fn drop(&mut self) {
+        let mut state = lock(&self.state);
+        state.remove_writer(self.id);
+        if state.closed {
+            self.wake_reader_half(&mut state);
+        }
+        unlock(state);
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl From<PipeWriter> for Box<dyn tokio::io::AsyncWrite + Unpin + Send> {
+    fn from(writer: PipeWriter) -> Self {
+        Box::new(writer)
+    }
+}
+
+#[cfg(feature = "futures")]
+impl From<PipeWriter> for Box<dyn futures::io::AsyncWrite + Unpin + Send> {
+    fn from(writer: PipeWriter) -> Self {
+        Box::new(writer)
+    }
+}